@@ -4,22 +4,61 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use anyhow::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
 use colored::*;
 
 use wbproto_beautifier::beautify;
-use wbproto_beautifier::{Arguments, Parser};
+use wbproto_beautifier::{
+    apply_edits, ast_json, compile_query, default_fields, documentation, expand, field_values,
+    find_first_error_node, find_nodes, generate, lint, parse, project_files, query_json,
+    query_matches, rename_def, rename_field, rename_instance_field, rules, sample_values,
+    severities, upgrade, Arguments, Commands, Config, Diagnostic, DocArguments, DocFormat, Emit,
+    ExpandArguments, FieldFilter, FindArguments, FindCriteria, Header, LintArguments, Parser,
+    QueryArguments, Random, Release, RenameDefArguments, RenameFieldArguments, Severity,
+    TestTemplatesArguments, UpgradeArguments,
+};
 
 fn main() {
     let mut options = Arguments::parse();
+    let result = match options.command.take() {
+        Some(Commands::Lint(arguments)) => lint_files(&options, &arguments),
+        Some(Commands::Expand(arguments)) => expand_files(&mut options, &arguments),
+        Some(Commands::TestTemplates(arguments)) => test_templates(&options, &arguments),
+        Some(Commands::Generate) => generate_files(&mut options),
+        Some(Commands::Doc(arguments)) => document_files(&options, &arguments),
+        Some(Commands::RenameField(arguments)) => rename_field_files(&mut options, &arguments),
+        Some(Commands::RenameDef(arguments)) => rename_def_file(&options, &arguments),
+        Some(Commands::Upgrade(arguments)) => upgrade_files(&mut options, &arguments),
+        Some(Commands::Query(arguments)) => query_files(&options, &arguments),
+        Some(Commands::Find(arguments)) => find_in_files(&options, &arguments),
+        None if options.emit == Some(Emit::AstJson) => emit_ast(&options),
+        None => {
+            beautify_files(&mut options);
+            Ok(true)
+        }
+    };
+    match result {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("{}", err.to_string().red());
+            std::process::exit(2);
+        }
+    }
+}
+
+fn beautify_files(options: &mut Arguments) {
     if options.files.is_empty() {
         options.inplace = false;
-        beautify_file(None, &mut options).unwrap();
+        beautify_file(None, options).unwrap();
     } else {
         options.inplace |= options.files.len() > 1;
         let files = options.files.clone();
         for file in files {
-            let r = beautify_file(Some(file), &mut options);
+            let r = beautify_file(Some(file), options);
             if let (false, Err(_)) = (options.inplace, &r) {
                 r.unwrap()
             } else if let Err(err) = r {
@@ -30,15 +69,22 @@ fn main() {
 }
 
 fn beautify_file(file: Option<String>, options: &mut Arguments) -> Result<()> {
-    let code = if let Some(file) = &file {
-        let mut file = std::fs::File::open(file)?;
-        read_to_string(&mut file, None)?.0 + "\n"
+    let source = read_source(file.as_deref())?;
+    let name = file.as_deref().unwrap_or("<stdin>");
+    if options.check_header {
+        for issue in Header::from_source(source.as_str()).issues {
+            let message = format!("{}:{}: {}", name, issue.row + 1, issue.message);
+            eprintln!("{}", message.yellow());
+        }
+    }
+    let code = if options.strip_defaults {
+        strip_defaults(source.as_str(), file.as_deref(), options)?
     } else {
-        read_to_string(&mut std::io::stdin(), None)?.0 + "\n"
+        source.clone()
     };
     let result = beautify(code.as_str(), options)?;
     let result_extra_newline = result.clone() + "\n";
-    if options.inplace && result_extra_newline != code {
+    if options.inplace && result_extra_newline != source {
         print!("{}", "file formatted ".green());
         match std::fs::write(file.unwrap().as_str(), result.as_bytes()) {
             Ok(_) => println!("{}", "and overwritten.".green()),
@@ -48,6 +94,584 @@ fn beautify_file(file: Option<String>, options: &mut Arguments) -> Result<()> {
     Ok(())
 }
 
+/// Removes the fields of `code` set to their default value, warning about the ones kept because
+/// their default changed between releases.
+fn strip_defaults(code: &str, file: Option<&str>, options: &Arguments) -> Result<String> {
+    let name = file.unwrap_or("<stdin>");
+    let config = load_config(options)?;
+    let mut edits = Vec::new();
+    for field in default_fields(code, file.map(Path::new), &config)? {
+        match field.removal {
+            Ok(edit) => edits.push(edit),
+            Err(release) => {
+                let message = format!(
+                    "{}:{}: kept {}, whose default changed in {}",
+                    name,
+                    field.row + 1,
+                    field.name,
+                    release
+                );
+                eprintln!("{}", message.yellow());
+            }
+        }
+    }
+    Ok(apply_edits(code, &edits))
+}
+
+/// Prints the syntax tree of every file as JSON.
+fn emit_ast(options: &Arguments) -> Result<bool> {
+    if options.files.is_empty() {
+        println!("{}", ast_json(read_source(None)?.as_str(), true)?);
+    } else {
+        let pretty = options.files.len() == 1;
+        for file in &options.files {
+            println!("{}", ast_json(read_source(Some(file))?.as_str(), pretty)?);
+        }
+    }
+    Ok(true)
+}
+
+/// Reads a file, or stdin if there is none, with a trailing newline added.
+fn read_source(file: Option<&str>) -> Result<String> {
+    let code = if let Some(file) = file {
+        let mut file = std::fs::File::open(file)?;
+        read_to_string(&mut file, None)?.0 + "\n"
+    } else {
+        read_to_string(&mut std::io::stdin(), None)?.0 + "\n"
+    };
+    Ok(code)
+}
+
+fn load_config(options: &Arguments) -> Result<Config> {
+    let mut config = match &options.config {
+        Some(path) => Config::load(Path::new(path))?,
+        None => Config::discover(&std::env::current_dir()?)?,
+    };
+    if let Some(webots_home) = &options.webots_home {
+        config.externproto.webots_home = Some(webots_home.into());
+    }
+    Ok(config)
+}
+
+/// Lints every file, printing the violations. Returns whether no error was found.
+fn lint_files(options: &Arguments, arguments: &LintArguments) -> Result<bool> {
+    if arguments.list_rules {
+        for rule in rules() {
+            println!(
+                "{:<24} {:<8} {}",
+                rule.id(),
+                rule.default_severity().to_string(),
+                rule.description()
+            );
+        }
+        return Ok(true);
+    }
+
+    let config = load_config(options)?;
+    let severities = severities(&config, &arguments.rules)?;
+    let files: Vec<Option<&str>> = if options.files.is_empty() {
+        vec![None]
+    } else {
+        options
+            .files
+            .iter()
+            .map(|file| Some(file.as_str()))
+            .collect()
+    };
+
+    let mut ok = true;
+    for file in files {
+        let mut code = read_source(file)?;
+        let name = file.unwrap_or("<stdin>");
+        let mut diagnostics = lint(code.as_str(), file.map(Path::new), &config, &severities)?;
+        if arguments.fix {
+            let edits: Vec<_> = diagnostics.iter().flat_map(|d| d.fix.clone()).collect();
+            if !edits.is_empty() {
+                code = apply_edits(code.as_str(), &edits);
+                let fixed = code.strip_suffix('\n').unwrap_or(code.as_str());
+                match file {
+                    Some(file) => {
+                        std::fs::write(file, fixed.as_bytes())?;
+                        eprintln!(
+                            "{}",
+                            format!("{name}: applied {} fixes", edits.len()).green()
+                        );
+                    }
+                    None => print!("{fixed}"),
+                }
+                diagnostics = lint(code.as_str(), file.map(Path::new), &config, &severities)?;
+            }
+        }
+        for diagnostic in diagnostics {
+            ok &= diagnostic.severity < Severity::Error;
+            print_diagnostic(name, &diagnostic);
+        }
+    }
+    Ok(ok)
+}
+
+/// Prints the expansion of every file, formatted.
+fn expand_files(options: &mut Arguments, arguments: &ExpandArguments) -> Result<bool> {
+    let config = load_config(options)?;
+    let values = field_values(&arguments.fields)?;
+    let files: Vec<Option<String>> = if options.files.is_empty() {
+        vec![None]
+    } else {
+        options.files.iter().cloned().map(Some).collect()
+    };
+
+    options.inplace = true;
+    for file in files {
+        let code = read_source(file.as_deref())?;
+        let expanded = expand(
+            code.as_str(),
+            file.as_deref().map(Path::new),
+            &values,
+            &config,
+        )?;
+        print!("{}", beautify(expanded.as_str(), options)?);
+    }
+    Ok(true)
+}
+
+/// Generates and formats the file described by every file, printing it, or writing it next to the
+/// description with several files or `--inplace`.
+fn generate_files(options: &mut Arguments) -> Result<bool> {
+    let write = options.inplace || options.files.len() > 1;
+    let files: Vec<Option<String>> = if options.files.is_empty() {
+        vec![None]
+    } else {
+        options.files.iter().cloned().map(Some).collect()
+    };
+
+    options.inplace = true;
+    for file in files {
+        let name = file.as_deref().unwrap_or("<stdin>");
+        let generated = generate(read_source(file.as_deref())?.as_str())
+            .map_err(|err| anyhow!("{name}: {err}"))?;
+        let code = beautify(generated.code.as_str(), options)
+            .map_err(|err| anyhow!("{name}: generated invalid code: {err}"))?;
+        match file.as_deref().filter(|_| write) {
+            Some(file) => {
+                let extension = if generated.proto { "proto" } else { "wbt" };
+                let path = Path::new(file).with_extension(extension);
+                std::fs::write(&path, code.as_bytes())?;
+                eprintln!("{}", format!("{name}: wrote {}", path.display()).green());
+            }
+            None => print!("{code}"),
+        }
+    }
+    Ok(true)
+}
+
+/// Prints the documentation of every file, or writes it to the output directory.
+fn document_files(options: &Arguments, arguments: &DocArguments) -> Result<bool> {
+    let extension = match arguments.format {
+        DocFormat::Markdown => "md",
+        DocFormat::Html => "html",
+    };
+    let files: Vec<Option<&str>> = if options.files.is_empty() {
+        vec![None]
+    } else {
+        options
+            .files
+            .iter()
+            .map(|file| Some(file.as_str()))
+            .collect()
+    };
+
+    for file in files {
+        let name = file.unwrap_or("<stdin>");
+        let code = read_source(file)?;
+        let documentation = documentation(code.as_str(), arguments.format)
+            .map_err(|err| anyhow!("{name}: {err}"))?;
+        match (&arguments.output, file) {
+            (Some(output), Some(file)) => {
+                let stem = Path::new(file).file_stem().unwrap_or_default();
+                let path =
+                    Path::new(output).join(format!("{}.{extension}", stem.to_string_lossy()));
+                std::fs::create_dir_all(output)?;
+                std::fs::write(&path, documentation.as_bytes())?;
+                eprintln!("{}", format!("{name}: wrote {}", path.display()).green());
+            }
+            (Some(_), None) => {
+                return Err(anyhow!("--output needs files to name the documentation"))
+            }
+            (None, _) => print!("{documentation}"),
+        }
+    }
+    Ok(true)
+}
+
+/// Renames a field of the PROTO given as file, reformatting it, and where the instances of the
+/// PROTO in the project set it. The PROTO is printed, and the project files that would change
+/// listed, unless `--inplace` is given to overwrite them.
+fn rename_field_files(options: &mut Arguments, arguments: &RenameFieldArguments) -> Result<bool> {
+    let file = match options.files.as_slice() {
+        [] => None,
+        [file] => Some(file.clone()),
+        _ => return Err(anyhow!("rename-field renames the field of a single PROTO")),
+    };
+    let name = file.as_deref().unwrap_or("<stdin>");
+    let code = read_source(file.as_deref())?;
+    let (proto, edits) = rename_field(code.as_str(), &arguments.from, &arguments.to)
+        .map_err(|err| anyhow!("{name}: {err}"))?;
+    let write = options.inplace;
+    options.inplace = true;
+    let renamed = beautify(apply_edits(code.as_str(), &edits).as_str(), options)?;
+    match &file {
+        Some(file) if write => {
+            std::fs::write(file, renamed.as_bytes())?;
+            eprintln!(
+                "{}",
+                format!("{name}: renamed {} occurrences", edits.len()).green()
+            );
+        }
+        _ => print!("{renamed}"),
+    }
+
+    let Some(project) = &arguments.project else {
+        return Ok(true);
+    };
+    let own_path = file
+        .as_deref()
+        .and_then(|file| Path::new(file).canonicalize().ok());
+    for path in project_files(Path::new(project))? {
+        if own_path.is_some() && path.canonicalize().ok() == own_path {
+            continue;
+        }
+        let name = path.display().to_string();
+        let code = read_source(Some(name.as_str()))?;
+        let edits = rename_instance_field(
+            code.as_str(),
+            proto.as_str(),
+            &arguments.from,
+            &arguments.to,
+        )
+        .map_err(|err| anyhow!("{name}: {err}"))?;
+        if edits.is_empty() {
+            continue;
+        }
+        if !write {
+            eprintln!(
+                "{name}: would rename {} occurrences, use --inplace to write them",
+                edits.len()
+            );
+            continue;
+        }
+        let renamed = apply_edits(code.as_str(), &edits);
+        let renamed = renamed.strip_suffix('\n').unwrap_or(renamed.as_str());
+        std::fs::write(&path, renamed.as_bytes())?;
+        eprintln!(
+            "{}",
+            format!("{name}: renamed {} occurrences", edits.len()).green()
+        );
+    }
+    Ok(true)
+}
+
+/// Renames a DEF name and its USEs in the file given, or in stdin, printing the result unless
+/// `--inplace` is given to overwrite the file.
+fn rename_def_file(options: &Arguments, arguments: &RenameDefArguments) -> Result<bool> {
+    let file = match options.files.as_slice() {
+        [] => None,
+        [file] => Some(file.as_str()),
+        _ => return Err(anyhow!("rename-def renames a name of a single file")),
+    };
+    let name = file.unwrap_or("<stdin>");
+    let code = read_source(file)?;
+    let edits = rename_def(
+        code.as_str(),
+        &arguments.from,
+        &arguments.to,
+        arguments.line,
+    )
+    .map_err(|err| anyhow!("{name}: {err}"))?;
+    let renamed = apply_edits(code.as_str(), &edits);
+    let renamed = renamed.strip_suffix('\n').unwrap_or(renamed.as_str());
+    match file {
+        Some(file) if options.inplace => {
+            std::fs::write(file, renamed.as_bytes())?;
+            eprintln!(
+                "{}",
+                format!("{name}: renamed {} occurrences", edits.len()).green()
+            );
+        }
+        _ => print!("{renamed}"),
+    }
+    Ok(true)
+}
+
+/// Upgrades every file, or the PROTO and world files of every directory, overwriting them with the
+/// formatted result. Stdin is upgraded to stdout. Returns whether every file could be upgraded.
+fn upgrade_files(options: &mut Arguments, arguments: &UpgradeArguments) -> Result<bool> {
+    let release = |text: &str| {
+        Release::parse(text).ok_or_else(|| anyhow!("\"{text}\" is not a release, as in R2023b"))
+    };
+    let from = arguments.from.as_deref().map(release).transpose()?;
+    let to = release(&arguments.to)?;
+    let config = load_config(options)?;
+
+    options.inplace = true;
+    let mut success = true;
+    for file in expand_directories(&options.files)? {
+        let name = file
+            .as_deref()
+            .map_or("<stdin>".to_string(), |file| file.display().to_string());
+        let code = read_source(file.as_deref().and_then(Path::to_str))?;
+        let upgraded = upgrade(code.as_str(), file.as_deref(), &config, from, to)
+            .and_then(|upgraded| beautify(upgraded.as_str(), options));
+        match (upgraded, &file) {
+            (Ok(upgraded), Some(file)) => {
+                if upgraded.clone() + "\n" != code {
+                    std::fs::write(file, upgraded.as_bytes())?;
+                    eprintln!("{}", format!("{name}: upgraded to {to}").green());
+                }
+            }
+            (Ok(upgraded), None) => print!("{upgraded}"),
+            (Err(err), _) => {
+                eprintln!("{}", format!("{name}: {err}").red());
+                success = false;
+            }
+        }
+    }
+    Ok(success)
+}
+
+/// Prints the captures of the matches of the query in every file, or in the PROTO and world files
+/// of every directory.
+fn query_files(options: &Arguments, arguments: &QueryArguments) -> Result<bool> {
+    let query = compile_query(&arguments.query)?;
+    let mut results = Vec::new();
+    for file in expand_directories(&options.files)? {
+        let name = file
+            .as_deref()
+            .map_or("<stdin>".to_string(), |file| file.display().to_string());
+        let code = read_source(file.as_deref().and_then(Path::to_str))?;
+        let matches =
+            query_matches(&query, code.as_str()).map_err(|err| anyhow!("{name}: {err}"))?;
+        results.push((name, matches));
+    }
+
+    if arguments.json {
+        println!("{}", query_json(&results)?);
+        return Ok(true);
+    }
+    for (name, matches) in &results {
+        for capture in matches.iter().flat_map(|m| &m.captures) {
+            let mut lines = capture.text.lines();
+            let first = lines.next().unwrap_or_default();
+            let more = if lines.next().is_some() { " ..." } else { "" };
+            println!(
+                "{}:{}:{}: {} {first}{more}",
+                name,
+                capture.line,
+                capture.column,
+                format!("@{}", capture.name).cyan()
+            );
+        }
+    }
+    Ok(true)
+}
+
+/// Prints the nodes matching the criteria in every file, or in the PROTO and world files of every
+/// directory, with the values of the fields the criteria name.
+fn find_in_files(options: &Arguments, arguments: &FindArguments) -> Result<bool> {
+    let criteria = FindCriteria {
+        node_types: arguments.nodes.clone(),
+        def_name: arguments.def_name.clone(),
+        fields: arguments
+            .fields
+            .iter()
+            .map(|field| field.parse::<FieldFilter>())
+            .collect::<Result<Vec<_>>>()?,
+    };
+    if criteria.node_types.is_empty() && criteria.def_name.is_none() && criteria.fields.is_empty() {
+        return Err(anyhow!("find needs --node, --def-name or --field"));
+    }
+
+    for file in expand_directories(&options.files)? {
+        let name = file
+            .as_deref()
+            .map_or("<stdin>".to_string(), |file| file.display().to_string());
+        let code = read_source(file.as_deref().and_then(Path::to_str))?;
+        let found = find_nodes(code.as_str(), &criteria).map_err(|err| anyhow!("{name}: {err}"))?;
+        for node in found {
+            let node_name = match &node.def {
+                Some(def) => format!("DEF {def} {}", node.node_type),
+                None => node.node_type.clone(),
+            };
+            let fields: Vec<String> = node
+                .fields
+                .iter()
+                .map(|field| {
+                    let mut lines = field.value.lines();
+                    let first = lines.next().unwrap_or_default();
+                    let more = if lines.next().is_some() { " ..." } else { "" };
+                    let default = if field.default { " (default)" } else { "" };
+                    format!("{} {first}{more}{default}", field.name)
+                })
+                .collect();
+            println!(
+                "{}:{}:{}: {} {}",
+                name,
+                node.line,
+                node.column,
+                node_name.cyan(),
+                fields.join(", ")
+            );
+        }
+    }
+    Ok(true)
+}
+
+/// The files given, with directories replaced by their PROTO and world files, or stdin, as `None`,
+/// if there are none.
+fn expand_directories(files: &[String]) -> Result<Vec<Option<PathBuf>>> {
+    if files.is_empty() {
+        return Ok(vec![None]);
+    }
+    let mut expanded = Vec::new();
+    for file in files {
+        let path = Path::new(file);
+        if path.is_dir() {
+            expanded.extend(project_files(path)?.into_iter().map(Some));
+        } else {
+            expanded.push(Some(path.to_path_buf()));
+        }
+    }
+    Ok(expanded)
+}
+
+/// Expands every file for its defaults and for random field values, reporting the values whose
+/// expansion fails, does not parse or has errors. Returns whether all expansions were valid.
+fn test_templates(options: &Arguments, arguments: &TestTemplatesArguments) -> Result<bool> {
+    let config = load_config(options)?;
+    let severities = severities(&config, &[])?;
+    let fixed = field_values(&arguments.fields)?;
+    let files: Vec<Option<&str>> = if options.files.is_empty() {
+        vec![None]
+    } else {
+        options
+            .files
+            .iter()
+            .map(|file| Some(file.as_str()))
+            .collect()
+    };
+
+    let mut ok = true;
+    for file in files {
+        let code = read_source(file)?;
+        let name = file.unwrap_or("<stdin>");
+        let path = file.map(Path::new);
+        let mut random = Random::new(arguments.seed);
+        let mut cases = vec![fixed.clone()];
+        for _ in 0..arguments.samples {
+            let mut values = sample_values(code.as_str(), &mut random)?;
+            values.extend(fixed.clone());
+            cases.push(values);
+        }
+
+        let mut failures = 0;
+        for values in &cases {
+            let problems = match expand(code.as_str(), path, values, &config) {
+                Err(err) => vec![err.to_string()],
+                Ok(expanded) => expansion_problems(expanded.as_str(), path, &config, &severities)?,
+            };
+            if problems.is_empty() {
+                continue;
+            }
+            failures += 1;
+            let values: Vec<String> = values.iter().map(|(k, v)| format!("{k}={v}")).collect();
+            let values = if values.is_empty() {
+                "defaults".to_string()
+            } else {
+                values.join(" ")
+            };
+            println!("{}: {} {}", name, "invalid expansion with".red(), values);
+            for problem in problems {
+                println!("  {problem}");
+            }
+        }
+        ok &= failures == 0;
+        let summary = format!(
+            "{name}: {} of {} expansions valid",
+            cases.len() - failures,
+            cases.len()
+        );
+        if failures == 0 {
+            eprintln!("{}", summary.green());
+        } else {
+            eprintln!("{}", summary.red());
+        }
+    }
+    Ok(ok)
+}
+
+/// The syntax error or the lint errors of an expanded PROTO, located in the generated text.
+fn expansion_problems(
+    expanded: &str,
+    path: Option<&Path>,
+    config: &Config,
+    severities: &HashMap<&'static str, Severity>,
+) -> Result<Vec<String>> {
+    let line = |row: usize| expanded.lines().nth(row).unwrap_or_default().trim();
+    let tree = parse(expanded)?;
+    if let Some(error_node) = find_first_error_node(tree.root_node()) {
+        let start = error_node.start_position();
+        return Ok(vec![format!(
+            "{}:{}: syntax error in \"{}\"",
+            start.row + 1,
+            start.column + 1,
+            line(start.row)
+        )]);
+    }
+
+    Ok(lint(expanded, path, config, severities)?
+        .into_iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .map(|diagnostic| {
+            let start = diagnostic.range.start_point;
+            format!(
+                "{}:{}: {} [{}] in \"{}\"",
+                start.row + 1,
+                start.column + 1,
+                diagnostic.message,
+                diagnostic.rule,
+                line(start.row)
+            )
+        })
+        .collect())
+}
+
+fn print_diagnostic(name: &str, diagnostic: &Diagnostic) {
+    let severity = match diagnostic.severity {
+        Severity::Error => "error".red(),
+        Severity::Warning => "warning".yellow(),
+        _ => "info".blue(),
+    };
+    let start = diagnostic.range.start_point;
+    println!(
+        "{}:{}:{}: {}[{}]: {}",
+        name,
+        start.row + 1,
+        start.column + 1,
+        severity,
+        diagnostic.rule,
+        diagnostic.message
+    );
+    for (range, note) in &diagnostic.related {
+        let start = range.start_point;
+        println!(
+            "  {}:{}:{}: {}",
+            name,
+            start.row + 1,
+            start.column + 1,
+            note
+        );
+    }
+}
+
 /// Taken from helix-editor
 /// Reads the first chunk from a Reader into the given buffer
 /// and detects the encoding.
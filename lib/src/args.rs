@@ -6,15 +6,23 @@
 
 pub use clap::CommandFactory;
 pub use clap::Parser;
+use clap::{Args, Subcommand, ValueEnum};
 
 static LONG_ABOUT: &str = "
 wbproto-beautifier formats and beautifies Webots PROTO code.
 
-This beautifier is quite opinionated and does not offer options.";
+This beautifier is quite opinionated and offers few options.";
 
 #[derive(Debug, Parser)]
 #[command(author, version, about = LONG_ABOUT)]
 pub struct Arguments {
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+
+    /// Prints the files in another form instead of formatting them. ast-json prints their syntax tree as JSON, one line per file if there are several.
+    #[arg(long = "emit", value_enum)]
+    pub emit: Option<Emit>,
+
     /// File(s) to beautify. If more than one file is passed, inline is implied. If no file is given, reads from stdin.
     #[arg(global = true)]
     pub files: Vec<String>,
@@ -22,4 +30,199 @@ pub struct Arguments {
     /// Whether files should be formatted inplace instead of printing to stdout.
     #[arg(global = true, long = "inplace")]
     pub inplace: bool,
+
+    /// Reports a missing or malformed header, unknown tags and unknown template languages on stderr.
+    #[arg(global = true, long = "check-header")]
+    pub check_header: bool,
+
+    /// Maximum line width, used when wrapping long restriction lists and comments.
+    #[arg(global = true, long = "max-width", default_value_t = 100)]
+    pub max_width: usize,
+
+    /// Rewraps blocks of full-line comments to the maximum width.
+    #[arg(global = true, long = "wrap-comments")]
+    pub wrap_comments: bool,
+
+    /// Indents the nodes between the braces of template blocks, as in `%< if (x) { >%`, as if the braces were nodes.
+    #[arg(global = true, long = "template-indent")]
+    pub template_indent: bool,
+
+    /// Sorts the fields of built-in nodes in the order of the Webots schema, as Webots saves worlds. Fields keep their comments, and nodes with template code are left as they are.
+    #[arg(global = true, long = "canonical-order")]
+    pub canonical_order: bool,
+
+    /// Removes the fields of nodes set to the default value of the built-in node or of the PROTO, when it can be found locally. Fields whose default changed between Webots releases are kept, with a warning.
+    #[arg(global = true, long = "strip-defaults")]
+    pub strip_defaults: bool,
+
+    /// Writes the files compactly instead of formatting them: comments other than the header, indentation and alignment are removed, while lines and template code are kept, so that formatting the result again gives the same file as formatting the original without comments.
+    #[arg(global = true, long = "minify")]
+    pub minify: bool,
+
+    /// Configuration file. Defaults to the first wbproto.toml found in the current directory or its parents.
+    #[arg(global = true, long = "config")]
+    pub config: Option<String>,
+
+    /// Local Webots installation `webots://` URLs are resolved against. Overrides the configuration file.
+    #[arg(global = true, long = "webots-home", env = "WEBOTS_HOME")]
+    pub webots_home: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Emit {
+    AstJson,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Checks files for mistakes Webots would only report at load time.
+    Lint(LintArguments),
+
+    /// Evaluates the JavaScript template of PROTOs and prints the resulting plain PROTOs.
+    Expand(ExpandArguments),
+
+    /// Expands template PROTOs for their defaults and random field values, and checks the results.
+    TestTemplates(TestTemplatesArguments),
+
+    /// Generates formatted PROTO or world files from the JSON of --emit ast-json or from JSON or YAML scene descriptions. The result is printed, or written next to each description with the .proto or .wbt extension if several files are given or with --inplace.
+    Generate,
+
+    /// Writes the reference documentation of PROTOs: their description, tags, license and interface fields.
+    Doc(DocArguments),
+
+    /// Renames an interface field of a PROTO in its declaration, IS bindings and template code, and prints the reformatted PROTO. With --inplace, the PROTO and the project files are overwritten.
+    RenameField(RenameFieldArguments),
+
+    /// Renames a DEF name and the USEs referring to it, refusing names already defined in the same scope, and prints the result. With --inplace, the file is overwritten.
+    RenameDef(RenameDefArguments),
+
+    /// Migrates files, or the PROTO and world files of directories, to a newer Webots release: the header, release URLs, renamed fields, Transform nodes turned into Pose and missing EXTERNPROTOs. The files are overwritten and reformatted.
+    Upgrade(UpgradeArguments),
+
+    /// Searches files, or the PROTO and world files of directories, with a tree-sitter query and prints the captures of every match.
+    Query(QueryArguments),
+
+    /// Finds the nodes of files, or of the PROTO and world files of directories, by type, DEF name and field values.
+    Find(FindArguments),
+}
+
+#[derive(Debug, Args)]
+pub struct LintArguments {
+    /// Changes the severity of a rule, as in `--rule header=off`. Severities are off, info, warning and error.
+    #[arg(long = "rule", value_name = "RULE=SEVERITY")]
+    pub rules: Vec<String>,
+
+    /// Lists the available rules and exits.
+    #[arg(long = "list-rules")]
+    pub list_rules: bool,
+
+    /// Applies the available fixes, overwriting the files, and reports what is left.
+    #[arg(long)]
+    pub fix: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ExpandArguments {
+    /// Value of a field, written as in a PROTO file, as in `--field size="1 2 3"`. Other fields keep their default.
+    #[arg(long = "field", value_name = "NAME=VALUE")]
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct TestTemplatesArguments {
+    /// Value of a field in every test, written as in a PROTO file. Other fields are sampled.
+    #[arg(long = "field", value_name = "NAME=VALUE")]
+    pub fields: Vec<String>,
+
+    /// Number of random samples, besides the defaults.
+    #[arg(long = "samples", default_value_t = 20)]
+    pub samples: usize,
+
+    /// Seed of the random samples, to reproduce a run.
+    #[arg(long = "seed", default_value_t = 0)]
+    pub seed: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct DocArguments {
+    /// Format of the documentation.
+    #[arg(long = "format", value_enum, default_value_t = DocFormat::Markdown)]
+    pub format: DocFormat,
+
+    /// Directory the documentation of every file is written to, named after the file, instead of printing it.
+    #[arg(long = "output", value_name = "DIR")]
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct RenameFieldArguments {
+    /// Current name of the field.
+    #[arg(long = "from")]
+    pub from: String,
+
+    /// New name of the field.
+    #[arg(long = "to")]
+    pub to: String,
+
+    /// Project directory whose worlds and PROTOs setting the field on instances of the PROTO are updated as well.
+    #[arg(long = "project", value_name = "DIR")]
+    pub project: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct RenameDefArguments {
+    /// Current DEF name.
+    #[arg(long = "from")]
+    pub from: String,
+
+    /// New DEF name.
+    #[arg(long = "to")]
+    pub to: String,
+
+    /// Line of the DEF to rename, when the name is defined more than once, as in a PROTO body and a field default.
+    #[arg(long = "line")]
+    pub line: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct UpgradeArguments {
+    /// Release the files were written for, as in `R2022b`. Defaults to the release of the header of each file.
+    #[arg(long = "from", value_name = "RELEASE")]
+    pub from: Option<String>,
+
+    /// Release to upgrade to, as in `R2023b`.
+    #[arg(long = "to", value_name = "RELEASE")]
+    pub to: String,
+}
+
+#[derive(Debug, Args)]
+pub struct QueryArguments {
+    /// Tree-sitter S-expression query, as in `(node . (_) @type (#eq? @type "Solid")) @solid`.
+    #[arg(long = "query", value_name = "QUERY")]
+    pub query: String,
+
+    /// Prints the matches as a JSON array instead of one `file:line:column` line per capture.
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct FindArguments {
+    /// Type of the nodes to find. Can be given several times to find nodes of any of the types.
+    #[arg(long = "node", value_name = "TYPE")]
+    pub nodes: Vec<String>,
+
+    /// Condition on a field, all of which must hold: `physics` if it is set, `!boundingObject` if it is not, or a comparison with a value, as in `width>640` or `name="wall"`. Unset fields of built-in nodes compare with their default.
+    #[arg(long = "field", value_name = "CONDITION")]
+    pub fields: Vec<String>,
+
+    /// Glob pattern the DEF name of the nodes must match, as in `LEFT_*`.
+    #[arg(long = "def-name", value_name = "GLOB")]
+    pub def_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DocFormat {
+    Markdown,
+    Html,
 }
@@ -0,0 +1,743 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tree_sitter::Node;
+
+use super::externproto::externprotos;
+use super::header::Header;
+use super::interface::{interface, protos};
+use super::syntax::{parse_valid, text, FieldDecl, NodeForm, Property};
+use super::types::{classify, Literal, ValueShape};
+
+/// A PROTO or world file, as exported by `--emit ast-json` and read back by `generate`. The
+/// structure is documented in the README and only grows backward compatible additions. Lines and
+/// layout flags may be left out when generating files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document {
+    #[serde(default)]
+    pub header: HeaderInfo,
+    #[serde(default)]
+    pub externprotos: Vec<ExternProtoInfo>,
+    /// The PROTO of a PROTO file, `null` for worlds.
+    pub proto: Option<Proto>,
+    /// The top-level nodes of a world file.
+    #[serde(default)]
+    pub nodes: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeaderInfo {
+    pub version: Option<String>,
+    pub encoding: Option<String>,
+    pub template_language: Option<String>,
+    pub license: Option<String>,
+    pub license_url: Option<String>,
+    pub documentation_url: Option<String>,
+    pub tags: Vec<String>,
+    pub keywords: Vec<String>,
+    /// The free text comments as written, `#` included. Empty entries stand for blank lines.
+    pub description: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternProtoInfo {
+    pub url: String,
+    #[serde(default)]
+    pub importable: bool,
+    #[serde(default)]
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proto {
+    pub name: String,
+    #[serde(default)]
+    pub fields: Vec<InterfaceField>,
+    /// The nodes and template blocks of the body.
+    #[serde(default)]
+    pub body: Vec<Value>,
+    #[serde(default)]
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterfaceField {
+    /// The declaring keyword, like `field` or `vrmlField`.
+    pub kind: String,
+    #[serde(rename = "type")]
+    pub field_type: String,
+    pub name: String,
+    pub default: FieldValue,
+    /// The allowed values, empty if any value is allowed.
+    #[serde(default)]
+    pub restrictions: Vec<Value>,
+    /// The full-line comments above the field, as written, `#` included.
+    #[serde(default)]
+    pub comments: Vec<String>,
+    /// The comment at the end of the line the field ends on.
+    pub trailing_comment: Option<String>,
+    #[serde(default)]
+    pub line: usize,
+}
+
+/// A value as written in the file: its literals, whether they were between brackets and whether
+/// the brackets spanned several lines.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FieldValue {
+    pub bracketed: bool,
+    #[serde(default)]
+    pub multiline: bool,
+    pub items: Vec<Value>,
+    /// The indices of the items followed by a comma, as in `[ 0 0 1, 1 0 0 ]`.
+    #[serde(default)]
+    pub commas: Vec<usize>,
+}
+
+/// A field set in a node body. `is` names the interface field it is bound to, in which case the
+/// value is empty.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeField {
+    pub name: String,
+    pub is: Option<String>,
+    #[serde(default)]
+    pub value: FieldValue,
+    /// The full-line comments above the field, as written, `#` included.
+    #[serde(default)]
+    pub comments: Vec<String>,
+    /// The comment at the end of the line the field ends on.
+    pub trailing_comment: Option<String>,
+    #[serde(default)]
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Value {
+    Bool {
+        value: bool,
+    },
+    Number {
+        value: f64,
+        #[serde(default)]
+        text: String,
+    },
+    String {
+        value: String,
+    },
+    Null,
+    Node {
+        def: Option<String>,
+        #[serde(rename = "type")]
+        node_type: String,
+        /// The fields of the body and the template blocks between them, in order.
+        #[serde(default)]
+        fields: Vec<NodeEntry>,
+        /// Whether the body spans several lines.
+        #[serde(default)]
+        multiline: bool,
+        /// The full-line comments above the node, as written, `#` included.
+        #[serde(default)]
+        comments: Vec<String>,
+        /// The comment at the end of the line the node ends on.
+        trailing_comment: Option<String>,
+        #[serde(default)]
+        line: usize,
+    },
+    Use {
+        name: String,
+        #[serde(default)]
+        line: usize,
+    },
+    /// A `%< ... >%` block, or a `%<= ... >%` expression, with its code as written.
+    Template {
+        #[serde(default)]
+        expression: bool,
+        code: String,
+        #[serde(default)]
+        line: usize,
+    },
+    /// Anything else, like a bare identifier.
+    Other {
+        text: String,
+    },
+}
+
+impl Value {
+    /// The comment at the end of the line a node ends on.
+    pub fn trailing_comment(&self) -> Option<&str> {
+        match self {
+            Value::Node {
+                trailing_comment, ..
+            } => trailing_comment.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NodeEntry {
+    Field(NodeField),
+    Template {
+        #[serde(default)]
+        expression: bool,
+        code: String,
+        #[serde(default)]
+        line: usize,
+    },
+}
+
+/// Parses `code` into its exported structure.
+pub fn ast(code: &str) -> Result<Document> {
+    let tree = parse_valid(code)?;
+    let root = tree.root_node();
+
+    let header = Header::from_source(code);
+    let header = HeaderInfo {
+        version: header.version.clone(),
+        encoding: header.encoding.clone(),
+        template_language: header.template_language.clone(),
+        license: header.license.clone(),
+        license_url: header.license_url.clone(),
+        documentation_url: header.documentation_url.clone(),
+        tags: header.tags.clone(),
+        keywords: header.keywords.clone(),
+        description: header
+            .description()
+            .iter()
+            .map(|line| line.to_string())
+            .collect(),
+    };
+
+    let externprotos = externprotos(root, code)
+        .into_iter()
+        .map(|externproto| ExternProtoInfo {
+            url: externproto.url.to_string(),
+            importable: externproto.importable,
+            line: line(externproto.node),
+        })
+        .collect();
+
+    let proto = protos(root).first().map(|proto| proto_info(*proto, code));
+
+    let mut cursor = root.walk();
+    let nodes = root
+        .children(&mut cursor)
+        .filter(|child| child.kind() == "node")
+        .map(|child| value(child, code))
+        .collect();
+
+    Ok(Document {
+        header,
+        externprotos,
+        proto,
+        nodes,
+    })
+}
+
+/// [`ast`], serialized as JSON, on a single line unless `pretty`.
+pub fn ast_json(code: &str, pretty: bool) -> Result<String> {
+    let document = ast(code)?;
+    Ok(if pretty {
+        serde_json::to_string_pretty(&document)?
+    } else {
+        serde_json::to_string(&document)?
+    })
+}
+
+fn proto_info(proto: Node, code: &str) -> Proto {
+    let name = proto
+        .child_by_field_name("proto")
+        .map(|name| text(name, code))
+        .unwrap_or_default();
+    let fields = interface(proto)
+        .into_iter()
+        .map(|field| interface_field(&field, code))
+        .collect();
+
+    // The body comes after the interface.
+    let mut cursor = proto.walk();
+    let body = proto
+        .children(&mut cursor)
+        .skip_while(|child| child.kind() != "]")
+        .filter(|child| {
+            matches!(
+                child.kind(),
+                "node" | "javascript_block" | "javascript_expression"
+            )
+        })
+        .map(|child| value(child, code))
+        .collect();
+
+    Proto {
+        name: name.to_string(),
+        fields,
+        body,
+        line: line(proto),
+    }
+}
+
+fn interface_field(field: &FieldDecl, code: &str) -> InterfaceField {
+    let (comments, trailing_comment) =
+        entry_comments(field.kind.parent().unwrap_or(field.kind), code);
+    InterfaceField {
+        kind: text(field.kind, code).to_string(),
+        field_type: text(field.field_type, code).to_string(),
+        name: text(field.name, code).to_string(),
+        default: field_value(&[field.value], code),
+        restrictions: field
+            .restrictions
+            .iter()
+            .map(|restriction| value(*restriction, code))
+            .collect(),
+        comments,
+        trailing_comment,
+        line: line(field.kind),
+    }
+}
+
+fn field_value(values: &[Node], code: &str) -> FieldValue {
+    let shape = ValueShape::new(values);
+    let mut commas = Vec::new();
+    let mut items = 0;
+    for value in values {
+        collect_commas(*value, &mut items, &mut commas);
+    }
+    FieldValue {
+        bracketed: shape.bracketed,
+        multiline: shape.bracketed
+            && values[0].start_position().row != values[0].end_position().row,
+        items: shape.items.iter().map(|item| value(*item, code)).collect(),
+        commas,
+    }
+}
+
+/// Counts the items of a value as `ValueShape` does, and collects the indices of those followed by
+/// a comma.
+fn collect_commas(node: Node, items: &mut usize, commas: &mut Vec<usize>) {
+    match node.kind() {
+        "[" | "]" | "comment" => {}
+        "," => {
+            if let Some(last) = items.checked_sub(1) {
+                commas.push(last);
+            }
+        }
+        "vector" => {
+            let mut cursor = node.walk();
+            for child in node.children(&mut cursor) {
+                collect_commas(child, items, commas);
+            }
+        }
+        _ => *items += 1,
+    }
+}
+
+fn value(node: Node, code: &str) -> Value {
+    let text = text(node, code);
+    match classify(node, code) {
+        Literal::Bool => Value::Bool {
+            value: text == "TRUE",
+        },
+        Literal::Int | Literal::Float => Value::Number {
+            value: number(text),
+            text: text.to_string(),
+        },
+        Literal::String => Value::String {
+            value: unquote(text),
+        },
+        Literal::Null => Value::Null,
+        Literal::Node => node_value(node, code),
+        Literal::Template => {
+            let mut cursor = node.walk();
+            let code_node = node.children(&mut cursor).find(|n| n.kind() == "code");
+            Value::Template {
+                expression: node.kind() == "javascript_expression",
+                code: code_node
+                    .map(|code_node| self::text(code_node, code).to_string())
+                    .unwrap_or_default(),
+                line: line(node),
+            }
+        }
+        Literal::Other => Value::Other {
+            text: text.to_string(),
+        },
+    }
+}
+
+fn node_value(node: Node, code: &str) -> Value {
+    let (def, node_type) = match NodeForm::new(node) {
+        Some(NodeForm::Use { name }) => {
+            return Value::Use {
+                name: text(name, code).to_string(),
+                line: line(node),
+            }
+        }
+        Some(NodeForm::Def { name, node_type }) => (Some(text(name, code).to_string()), node_type),
+        Some(NodeForm::Plain { node_type }) => (None, node_type),
+        None => {
+            return Value::Other {
+                text: text(node, code).to_string(),
+            }
+        }
+    };
+
+    let mut cursor = node.walk();
+    let fields = node
+        .children(&mut cursor)
+        .filter_map(|child| match child.kind() {
+            "property" => Property::new(child).map(|property| {
+                let (comments, trailing_comment) = entry_comments(child, code);
+                NodeEntry::Field(NodeField {
+                    name: text(property.name, code).to_string(),
+                    is: property
+                        .is_target
+                        .map(|target| text(target, code).to_string()),
+                    value: field_value(&property.values, code),
+                    comments,
+                    trailing_comment,
+                    line: line(child),
+                })
+            }),
+            "javascript_block" | "javascript_expression" => match value(child, code) {
+                Value::Template {
+                    expression,
+                    code,
+                    line,
+                } => Some(NodeEntry::Template {
+                    expression,
+                    code,
+                    line,
+                }),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+
+    let (comments, trailing_comment) = entry_comments(node, code);
+    Value::Node {
+        def,
+        node_type: text(node_type, code).to_string(),
+        fields,
+        multiline: node.start_position().row != node.end_position().row,
+        comments,
+        trailing_comment,
+        line: line(node),
+    }
+}
+
+/// The full-line comments between `node` and the entry before it, and the comment on the line it
+/// ends on. The comments at the top of a file are its header.
+fn entry_comments(node: Node, code: &str) -> (Vec<String>, Option<String>) {
+    let mut comments = Vec::new();
+    let mut previous = node.prev_sibling();
+    while let Some(comment) = previous.filter(|previous| previous.kind() == "comment") {
+        previous = comment.prev_sibling();
+        // A comment on the line the previous entry ends on trails it.
+        if previous
+            .is_some_and(|previous| previous.end_position().row == comment.start_position().row)
+        {
+            break;
+        }
+        comments.push(text(comment, code).to_string());
+    }
+    if previous.is_none()
+        && node
+            .parent()
+            .is_some_and(|parent| parent.kind() == "document")
+    {
+        comments.clear();
+    }
+    comments.reverse();
+
+    let trailing_comment = node
+        .next_sibling()
+        .filter(|next| {
+            next.kind() == "comment" && next.start_position().row == node.end_position().row
+        })
+        .map(|comment| text(comment, code).to_string());
+    (comments, trailing_comment)
+}
+
+fn number(text: &str) -> f64 {
+    let digits = text.trim_start_matches(['-', '+']);
+    let sign = if text.starts_with('-') { -1.0 } else { 1.0 };
+    match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => sign * u64::from_str_radix(hex, 16).unwrap_or_default() as f64,
+        None => text.parse().unwrap_or_default(),
+    }
+}
+
+/// The content of a string literal, with its escapes resolved.
+fn unquote(text: &str) -> String {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .unwrap_or(text);
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            unquoted.extend(chars.next());
+        } else {
+            unquoted.push(c);
+        }
+    }
+    unquoted
+}
+
+/// The 1-based line `node` starts on.
+fn line(node: Node) -> usize {
+    node.start_position().row + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALL: &str = r#"#VRML_SIM R2023b utf8
+# template language: javascript
+# tags: nonDeterministic
+EXTERNPROTO "Brick.proto"
+IMPORTABLE EXTERNPROTO "webots://projects/Door.proto"
+
+PROTO Wall [
+  field SFVec3f size 1 2 0x3
+  field SFString{"brick", "stone"} material "brick"
+  vrmlField MFString urls [
+    "a \"b\".png"
+  ]
+]
+{
+  DEF WALL Solid {
+    name IS material
+    locked TRUE
+    boundingObject USE SHAPE
+    %<= fields.size.value.x >%
+    children [ Shape { } NULL ]
+  }
+}
+"#;
+
+    #[test]
+    fn header_and_externprotos() {
+        let document = ast(WALL).unwrap();
+        let header = &document.header;
+        assert_eq!(header.version.as_deref(), Some("R2023b"));
+        assert_eq!(header.encoding.as_deref(), Some("utf8"));
+        assert_eq!(header.template_language.as_deref(), Some("javascript"));
+        assert_eq!(header.tags, ["nonDeterministic"]);
+        assert_eq!(header.license, None);
+
+        let externprotos: Vec<(&str, bool, usize)> = document
+            .externprotos
+            .iter()
+            .map(|e| (e.url.as_str(), e.importable, e.line))
+            .collect();
+        assert_eq!(
+            externprotos,
+            [
+                ("Brick.proto", false, 4),
+                ("webots://projects/Door.proto", true, 5)
+            ]
+        );
+        assert!(document.nodes.is_empty());
+    }
+
+    #[test]
+    fn interface_fields() {
+        let proto = ast(WALL).unwrap().proto.unwrap();
+        assert_eq!((proto.name.as_str(), proto.line), ("Wall", 7));
+        let fields: Vec<(&str, &str, &str, usize)> = proto
+            .fields
+            .iter()
+            .map(|f| {
+                (
+                    f.kind.as_str(),
+                    f.field_type.as_str(),
+                    f.name.as_str(),
+                    f.line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("field", "SFVec3f", "size", 8),
+                ("field", "SFString", "material", 9),
+                ("vrmlField", "MFString", "urls", 10),
+            ]
+        );
+
+        let size = &proto.fields[0].default;
+        assert!(!size.bracketed);
+        let numbers: Vec<f64> = size
+            .items
+            .iter()
+            .map(|item| match item {
+                Value::Number { value, .. } => *value,
+                item => panic!("{item:?}"),
+            })
+            .collect();
+        assert_eq!(numbers, [1.0, 2.0, 3.0]);
+
+        let restrictions = &proto.fields[1].restrictions;
+        assert!(
+            matches!(&restrictions[..], [Value::String { value: a }, Value::String { value: b }] if a == "brick" && b == "stone")
+        );
+
+        let urls = &proto.fields[2].default;
+        assert!(urls.bracketed && urls.multiline);
+        assert!(matches!(&urls.items[..], [Value::String { value }] if value == "a \"b\".png"));
+    }
+
+    #[test]
+    fn body_nodes() {
+        let proto = ast(WALL).unwrap().proto.unwrap();
+        let [Value::Node {
+            def,
+            node_type,
+            fields,
+            multiline,
+            line,
+            ..
+        }] = &proto.body[..]
+        else {
+            panic!("{:?}", proto.body);
+        };
+        assert_eq!(def.as_deref(), Some("WALL"));
+        assert_eq!((node_type.as_str(), *multiline, *line), ("Solid", true, 15));
+
+        let [NodeEntry::Field(name), NodeEntry::Field(locked), NodeEntry::Field(bounding), NodeEntry::Template {
+            expression: true,
+            code,
+            line: 19,
+        }, NodeEntry::Field(children)] = &fields[..]
+        else {
+            panic!("{fields:?}");
+        };
+        assert_eq!(name.is.as_deref(), Some("material"));
+        assert!(name.value.items.is_empty());
+        assert!(matches!(
+            locked.value.items[..],
+            [Value::Bool { value: true }]
+        ));
+        assert!(matches!(&bounding.value.items[..], [Value::Use { name, .. }] if name == "SHAPE"));
+        assert_eq!(code.trim(), "fields.size.value.x");
+        assert!(children.value.bracketed && !children.value.multiline);
+        assert!(matches!(
+            &children.value.items[..],
+            [Value::Node { node_type, multiline: false, .. }, Value::Null] if node_type == "Shape"
+        ));
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        let code = r#"#VRML_SIM R2023b utf8
+# A wall.
+
+PROTO Wall [
+  # The size of the wall,
+  # in meters.
+  field SFVec3f size 1 2 3  # Width, depth and height.
+  field SFString name "wall"
+]
+{
+  # The wall itself.
+  Solid {
+    name IS name  # Bound.
+    children [
+      # Its shape.
+      Shape { }  # Empty.
+    ]
+  }
+}
+"#;
+        let proto = ast(code).unwrap().proto.unwrap();
+        let comments =
+            |field: &InterfaceField| (field.comments.clone(), field.trailing_comment.clone());
+        assert_eq!(
+            comments(&proto.fields[0]),
+            (
+                vec![
+                    "# The size of the wall,".to_string(),
+                    "# in meters.".to_string()
+                ],
+                Some("# Width, depth and height.".to_string())
+            )
+        );
+        assert_eq!(comments(&proto.fields[1]), (vec![], None));
+
+        let [Value::Node {
+            comments,
+            trailing_comment: None,
+            fields,
+            ..
+        }] = &proto.body[..]
+        else {
+            panic!("{:?}", proto.body);
+        };
+        assert_eq!(comments, &["# The wall itself."]);
+        let [NodeEntry::Field(name), NodeEntry::Field(children)] = &fields[..] else {
+            panic!("{fields:?}");
+        };
+        assert!(name.comments.is_empty());
+        assert_eq!(name.trailing_comment.as_deref(), Some("# Bound."));
+        assert!(matches!(
+            &children.value.items[..],
+            [Value::Node { comments, trailing_comment: Some(trailing), .. }]
+                if comments == &["# Its shape."] && trailing == "# Empty."
+        ));
+
+        let world =
+            ast("#VRML_SIM R2023b utf8\n# Header.\nWorldInfo {\n}\n# A node.\nSolid {\n}\n")
+                .unwrap();
+        let comments: Vec<Vec<String>> = world
+            .nodes
+            .iter()
+            .map(|node| match node {
+                Value::Node { comments, .. } => comments.clone(),
+                node => panic!("{node:?}"),
+            })
+            .collect();
+        assert_eq!(comments, [vec![], vec!["# A node.".to_string()]]);
+    }
+
+    #[test]
+    fn worlds_have_top_level_nodes() {
+        let document = ast("#VRML_SIM R2023b utf8\nWorldInfo {\n}\nDEF A Pose {\n}\n").unwrap();
+        assert!(document.proto.is_none());
+        assert_eq!(document.nodes.len(), 2);
+        let json = ast_json("#VRML_SIM R2023b utf8\nWorldInfo {\n}\n", false).unwrap();
+        assert!(!json.contains('\n'));
+        assert!(json.contains(r#""proto":null"#));
+        assert!(json.contains(r#""kind":"node""#));
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        assert_eq!(
+            ast("#VRML_SIM R2023b utf8\nSolid {\n  name ]\n}\n")
+                .unwrap_err()
+                .to_string(),
+            "Parsed file contain errors (at line 3)."
+        );
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(number("-0x10"), -16.0);
+        assert_eq!(number("1e-3"), 0.001);
+        assert_eq!(number("+2"), 2.0);
+        assert_eq!(unquote(r#""a \"quoted\" \\ path""#), r#"a "quoted" \ path"#);
+    }
+}
@@ -8,7 +8,11 @@ use std::io::Write;
 use std::process::{Command, Stdio};
 
 use super::args::Arguments;
-use anyhow::{anyhow, Context, Result};
+use super::header::{Header, Release, HEADER_KEYS};
+use super::minify::minify;
+use super::schema::node_fields;
+use super::syntax::{find_first_error_node, parse, FieldDecl, NodeForm};
+use anyhow::{anyhow, Result};
 use tree_sitter::Node;
 
 struct State<'a> {
@@ -20,11 +24,18 @@ struct State<'a> {
     level: usize,
     extra_indentation: usize,
     num_spaces: usize,
+    /// Source bytes already printed, by a reflowed comment block or a verbatim region.
+    printed_from: usize,
+    printed_until: usize,
+    /// Braces left open by template blocks, when they are indented as nesting levels.
+    template_level: usize,
+    /// The release named in the header, whose schema orders the fields of built-in nodes.
+    release: Option<Release>,
 }
 
 impl State<'_> {
     fn indent(&mut self) {
-        for _ in 0..self.level {
+        for _ in 0..self.level + self.template_level {
             self.print(" ".repeat(self.num_spaces).as_str());
         }
         for _ in 0..self.extra_indentation {
@@ -56,6 +67,40 @@ impl State<'_> {
         self.col = 0;
         self.row += 1;
     }
+
+    /// Prints source text as is, keeping track of the lines it spans.
+    fn print_raw(&mut self, string: &str) {
+        self.print(string);
+        if let Some(last_newline) = string.rfind('\n') {
+            self.row += string.matches('\n').count();
+            self.col = string.len() - last_newline - 1;
+        }
+    }
+
+    /// Whether `node` was already printed, as part of a reflowed comment block or of a region the
+    /// formatter was told to ignore.
+    fn is_printed(&self, node: Node) -> bool {
+        self.printed_from <= node.start_byte() && node.start_byte() < self.printed_until
+    }
+
+    /// Leaves the nesting levels closed by `node`, if it is a template block starting with
+    /// closing braces. To be called before indenting the line the block starts.
+    fn close_template(&mut self, node: Node) {
+        if let Some((closes, _)) = self.template_braces(node) {
+            self.template_level = self.template_level.saturating_sub(closes);
+        }
+    }
+
+    /// The braces a template block closes first and then leaves open, as in `} else {`, when
+    /// template-aware indentation is on.
+    fn template_braces(&self, node: Node) -> Option<(usize, usize)> {
+        if !self.arguments.template_indent || node.kind() != "javascript_block" {
+            return None;
+        }
+        let mut cursor = node.walk();
+        let code = node.children(&mut cursor).find(|n| n.kind() == "code")?;
+        Some(braces(code.utf8_text(self.code).ok()?))
+    }
 }
 
 trait TraversingError<T> {
@@ -75,15 +120,16 @@ impl<T> TraversingError<T> for Option<T> {
 }
 
 pub fn beautify(code: &str, arguments: &mut Arguments) -> Result<String> {
-    let mut parser = tree_sitter::Parser::new();
-    parser
-        .set_language(&tree_sitter_wbproto::language())
-        .with_context(|| "Could not set Tree-Sitter language")?;
-
-    let tree = parser
-        .parse(code, None)
-        .ok_or_else(|| anyhow!("Could not parse file."))?;
+    if arguments.minify {
+        let minified = minify(code)?;
+        if arguments.inplace {
+            return Ok(minified);
+        }
+        print!("{minified}");
+        return Ok(String::new());
+    }
 
+    let tree = parse(code)?;
     let root = tree.root_node();
     if root.has_error() {
         let error_node = find_first_error_node(root)
@@ -101,6 +147,10 @@ pub fn beautify(code: &str, arguments: &mut Arguments) -> Result<String> {
         extra_indentation: 0,
         formatted: String::with_capacity(code.len() * 2),
         num_spaces: 2,
+        printed_from: 0,
+        printed_until: 0,
+        template_level: 0,
+        release: Header::from_source(code).release(),
     };
 
     format_document(&mut state, root)?;
@@ -108,24 +158,42 @@ pub fn beautify(code: &str, arguments: &mut Arguments) -> Result<String> {
     Ok(state.formatted)
 }
 
-fn find_first_error_node(node: tree_sitter::Node) -> Option<tree_sitter::Node> {
-    if node.is_error() {
-        return Some(node.clone());
-    }
-    for child in node.children(&mut node.walk()) {
-        if let Some(error_node) = find_first_error_node(child.clone()) {
-            return Some(error_node);
-        }
-    }
-    None
-}
-
 fn format_document(state: &mut State, node: Node) -> Result<()> {
     let mut cursor = node.walk();
     let children: Vec<Node> = node.children(&mut cursor).collect();
     let mut last_node = node;
-    for child in children {
+
+    let header_len = children
+        .iter()
+        .take_while(|child| {
+            child.kind() == "comment"
+                && directive(child.utf8_text(state.code).unwrap_or_default()).is_none()
+        })
+        .count();
+    let header = Header::parse(
+        children[..header_len]
+            .iter()
+            .map(|child| Ok((child.start_position().row, child.utf8_text(state.code)?)))
+            .collect::<Result<Vec<_>>>()?,
+    );
+    let children = if header.is_structured() {
+        let mut lines = header.lines();
+        if state.arguments.wrap_comments {
+            let texts: Vec<&str> = lines.iter().map(String::as_str).collect();
+            lines = reflow(&texts, state.arguments.max_width);
+        }
+        for line in lines {
+            state.println(line.as_str());
+        }
+        last_node = children[header_len - 1];
+        &children[header_len..]
+    } else {
+        &children[..]
+    };
+
+    for &child in children {
         match child.kind() {
+            _ if state.is_printed(child) => {}
             "comment" => {
                 if last_node.end_position().row == child.start_position().row {
                     state.print(" ");
@@ -176,6 +244,16 @@ fn format_node(state: &mut State, node: Node) -> Result<()> {
 }
 
 fn format_comment(state: &mut State, node: Node) -> Result<()> {
+    let directive = directive(node.utf8_text(state.code)?);
+    if directive.is_none() && state.arguments.wrap_comments && is_full_line(node) {
+        let block = comment_block(node, state.code);
+        if block.len() > 1
+            || node.utf8_text(state.code)?.len() + state.col > state.arguments.max_width
+        {
+            return format_comment_block(state, block);
+        }
+    }
+
     let text = node.utf8_text(state.code)?;
     let mut stripped = false;
     let line = if text.trim().starts_with("##") {
@@ -191,9 +269,153 @@ fn format_comment(state: &mut State, node: Node) -> Result<()> {
         }
     }
     state.print(line);
+
+    if let Some(directive) = directive {
+        format_verbatim(state, node, directive)?;
+    }
     Ok(())
 }
 
+/// The formatter directive in a comment, if any: `off`, `on` or `ignore-next`, as in
+/// `# wbproto-beautifier: off`.
+fn directive(text: &str) -> Option<&str> {
+    let body = text.trim().trim_start_matches('#').trim();
+    let directive = body.strip_prefix("wbproto-beautifier:")?.trim();
+    matches!(directive, "off" | "on" | "ignore-next").then_some(directive)
+}
+
+/// Copies the source following a directive comment as is: up to and including the matching
+/// `on` directive for `off`, or up to and including the next node for `ignore-next`. The region
+/// never extends past the closing bracket or brace of the enclosing node.
+fn format_verbatim(state: &mut State, node: Node, mode: &str) -> Result<()> {
+    let mut end = None;
+    let mut next = node.next_sibling();
+    while let Some(sibling) = next {
+        if matches!(sibling.kind(), "]" | "}") {
+            break;
+        }
+        end = Some(sibling.end_byte());
+        let is_comment = sibling.kind() == "comment";
+        match mode {
+            "ignore-next" if !is_comment => break,
+            "off" if is_comment && directive(sibling.utf8_text(state.code)?) == Some("on") => break,
+            _ => next = sibling.next_sibling(),
+        }
+    }
+
+    if let Some(end) = end {
+        let text = std::str::from_utf8(&state.code[node.end_byte()..end])?;
+        state.print_raw(text);
+        state.printed_from = node.end_byte();
+        state.printed_until = end;
+    }
+    Ok(())
+}
+
+fn is_full_line(node: Node) -> bool {
+    match node.prev_sibling() {
+        Some(prev) => prev.end_position().row < node.start_position().row,
+        None => true,
+    }
+}
+
+/// The run of full-line comments starting at `node`: comments on consecutive lines, all starting at
+/// the same column. Directives are never part of a run.
+fn comment_block<'a>(node: Node<'a>, code: &[u8]) -> Vec<Node<'a>> {
+    let mut block = vec![node];
+    let mut last = node;
+    while let Some(next) = last.next_sibling() {
+        if next.kind() != "comment"
+            || directive(next.utf8_text(code).unwrap_or_default()).is_some()
+            || next.start_position().row != last.end_position().row + 1
+            || next.start_position().column != node.start_position().column
+        {
+            break;
+        }
+        block.push(next);
+        last = next;
+    }
+    block
+}
+
+fn format_comment_block(state: &mut State, block: Vec<Node>) -> Result<()> {
+    let texts = block
+        .iter()
+        .map(|node| node.utf8_text(state.code))
+        .collect::<Result<Vec<_>, _>>()?;
+    let width = state.arguments.max_width.saturating_sub(state.col);
+    for (i, line) in reflow(&texts, width).iter().enumerate() {
+        if i != 0 {
+            state.println("");
+            state.indent();
+        }
+        state.print(line);
+    }
+    if let (Some(first), Some(last)) = (block.first(), block.last()) {
+        state.printed_from = first.start_byte();
+        state.printed_until = last.end_byte();
+    }
+    Ok(())
+}
+
+/// Rewraps comment lines to `width` columns. Blank lines and empty comments separate paragraphs,
+/// while banners (`##`), list items, header lines and formatter directives are kept as they are.
+fn reflow(lines: &[&str], width: usize) -> Vec<String> {
+    let width = width.saturating_sub(2);
+    let mut reflowed = Vec::new();
+    let mut words: Vec<&str> = Vec::new();
+
+    let flush = |words: &mut Vec<&str>, reflowed: &mut Vec<String>| {
+        let mut line = String::new();
+        for word in words.drain(..) {
+            if !line.is_empty() && line.len() + 1 + word.len() > width {
+                reflowed.push(format!("# {line}"));
+                line.clear();
+            }
+            if !line.is_empty() {
+                line += " ";
+            }
+            line += word;
+        }
+        if !line.is_empty() {
+            reflowed.push(format!("# {line}"));
+        }
+    };
+
+    for line in lines {
+        let text = line.trim();
+        let body = text.strip_prefix('#').unwrap_or(text).trim();
+        let is_list = body.starts_with("- ")
+            || body.starts_with("* ")
+            || body.starts_with("+ ")
+            || body.split_once(['.', ')']).is_some_and(|(n, rest)| {
+                !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) && rest.starts_with(' ')
+            });
+        let is_key = body
+            .split_once(':')
+            .is_some_and(|(key, _)| HEADER_KEYS.contains(&key.trim().to_lowercase().as_str()));
+
+        if text.is_empty() {
+            flush(&mut words, &mut reflowed);
+            reflowed.push(String::new());
+        } else if text.starts_with("##") || text.starts_with("#VRML") {
+            flush(&mut words, &mut reflowed);
+            reflowed.push(text.to_string());
+        } else if body.is_empty() {
+            flush(&mut words, &mut reflowed);
+            reflowed.push("#".to_string());
+        } else if is_list || is_key {
+            flush(&mut words, &mut reflowed);
+            reflowed.push(format!("# {body}"));
+        } else {
+            words.extend(body.split_whitespace());
+        }
+    }
+    flush(&mut words, &mut reflowed);
+
+    reflowed
+}
+
 fn format_extern(state: &mut State, node: Node) -> Result<()> {
     let mut cursor = node.walk();
     let children: Vec<Node> = node.children(&mut cursor).collect();
@@ -214,54 +436,41 @@ fn format_proto(state: &mut State, node: Node) -> Result<()> {
         .named_children(&mut cursor)
         .filter(|n| n.kind() == "field")
         .collect();
-    let sizes = field_sizes(state, fields);
+    state.level = 1;
+    let sizes = field_sizes(state, fields)?;
 
     state.print("PROTO ");
     state.print(name.utf8_text(state.code)?);
     state.print(" [");
-    state.level = 1;
     let mut last_line = 0;
     let mut ok = false;
     for child in node.children(&mut cursor) {
         match (child.kind(), ok) {
             ("[", false) => ok = true,
             ("]", true) => ok = false,
+            (_, true) if state.is_printed(child) => last_line = child.range().end_point.row,
             ("field", true) => {
                 state.println("");
                 state.indent();
                 last_line = child.range().end_point.row;
+                let field = FieldDecl::new(child)?;
                 let mut at = state.level * state.num_spaces + sizes.0;
-                let mut ccursor = node.walk();
-                let fields: Vec<Node> = child.children(&mut ccursor).collect();
-                format_node(
-                    state,
-                    *fields
-                        .first()
-                        .ok_or_else(|| anyhow!("Could not extract field kind"))?,
-                )?;
+                format_node(state, field.kind)?;
                 state.print(" ".repeat(at.saturating_sub(state.col)).as_str());
-                format_node(
-                    state,
-                    *fields
-                        .get(1)
-                        .ok_or_else(|| anyhow!("Could not extract field type"))?,
-                )?;
+                let cell = type_cell(state, &field, at)?;
+                for (i, line) in cell.iter().enumerate() {
+                    if i != 0 {
+                        state.println("");
+                        state.print(" ".repeat(at).as_str());
+                    }
+                    state.print(line);
+                }
                 at += sizes.1;
                 state.print(" ".repeat(at.saturating_sub(state.col)).as_str());
-                format_node(
-                    state,
-                    *fields
-                        .get(2)
-                        .ok_or_else(|| anyhow!("Could not extract field name"))?,
-                )?;
+                format_node(state, field.name)?;
                 at += sizes.2;
                 state.print(" ".repeat(at.saturating_sub(state.col)).as_str());
-                format_node(
-                    state,
-                    *fields
-                        .get(3)
-                        .ok_or_else(|| anyhow!("Could not extract field value"))?,
-                )?;
+                format_node(state, field.value)?;
             }
             ("comment", true) => {
                 if child.range().start_point.row != last_line {
@@ -288,63 +497,117 @@ fn format_proto(state: &mut State, node: Node) -> Result<()> {
                 ok = true;
                 continue;
             }
-            ("node", true) => format_node_def(state, child)?,
-            ("comment", true) => format_comment(state, child)?,
-            ("javascript_block", true) => format_node(state, child)?,
+            (_, true) if state.is_printed(child) => continue,
+            ("node" | "comment" | "javascript_block", true) => {
+                state.close_template(child);
+                state.indent();
+                format_node(state, child)?;
+            }
             (_, _) => continue,
         }
         state.println("");
     }
+    state.template_level = 0;
     state.print("}");
     Ok(())
 }
 
-fn field_sizes(state: &mut State, fields: Vec<Node>) -> (usize, usize, usize, usize) {
+fn field_sizes(state: &mut State, fields: Vec<Node>) -> Result<(usize, usize, usize, usize)> {
+    let fields = fields
+        .into_iter()
+        .map(FieldDecl::new)
+        .collect::<Result<Vec<_>>>()?;
+    let padding = state.num_spaces;
+
     let mut kind_size = 0usize;
+    for field in &fields {
+        let text_kind = render(state, field.kind)?;
+        kind_size = std::cmp::max(kind_size, text_kind.len() + padding);
+    }
+
     let mut type_size = 0usize;
     let mut name_size = 0usize;
     let mut value_size = 0usize;
+    let at = state.level * state.num_spaces + kind_size;
+    for field in &fields {
+        let cell = type_cell(state, field, at)?;
+        let text_type = cell.last().map(String::as_str).unwrap_or_default();
+        let text_name = render(state, field.name)?;
+        let text_value = render(state, field.value)?;
 
-    let saved_formatted = state.formatted.clone();
-    let saved_inplace = state.arguments.inplace;
-    state.formatted.clear();
-    state.arguments.inplace = true;
-
-    for field in fields {
-        let mut cursor = field.walk();
-        let children: Vec<Node> = field.children(&mut cursor).collect();
+        type_size = std::cmp::max(type_size, text_type.len() + padding);
+        name_size = std::cmp::max(name_size, text_name.len() + padding);
+        value_size = std::cmp::max(value_size, text_value.len() + padding);
+    }
 
-        let node_kind = children.first().unwrap();
-        format_node(state, *node_kind).unwrap();
-        let text_kind = state.formatted.clone();
-        state.formatted.clear();
+    Ok((kind_size, type_size, name_size, value_size))
+}
 
-        let node_type = children.get(1).unwrap();
-        format_node(state, *node_type).unwrap();
-        let text_type = state.formatted.clone();
-        state.formatted.clear();
+/// Formats `node` into a string instead of the output.
+fn render(state: &mut State, node: Node) -> Result<String> {
+    let saved_formatted = std::mem::take(&mut state.formatted);
+    let saved_inplace = state.arguments.inplace;
+    let (saved_col, saved_row) = (state.col, state.row);
+    let saved_printed = (state.printed_from, state.printed_until);
+    let saved_template_level = state.template_level;
+    state.arguments.inplace = true;
 
-        let node_name = children.get(2).unwrap();
-        format_node(state, *node_name).unwrap();
-        let text_name = state.formatted.clone();
-        state.formatted.clear();
+    let result = format_node(state, node);
+    let text = std::mem::replace(&mut state.formatted, saved_formatted);
+    state.arguments.inplace = saved_inplace;
+    state.col = saved_col;
+    state.row = saved_row;
+    (state.printed_from, state.printed_until) = saved_printed;
+    state.template_level = saved_template_level;
 
-        let node_value = children.get(3).unwrap();
-        format_node(state, *node_value).unwrap();
-        let text_value = state.formatted.clone();
-        state.formatted.clear();
+    result.map(|_| text)
+}
 
-        let padding = state.num_spaces;
-        kind_size = std::cmp::max(kind_size, text_kind.len() + padding);
-        type_size = std::cmp::max(type_size, text_type.len() + padding);
-        name_size = std::cmp::max(name_size, text_name.len() + padding);
-        value_size = std::cmp::max(value_size, text_value.len() + padding);
+/// The lines of the type column of a field: the type itself followed by its restriction list, if
+/// any. Restrictions are kept on one line unless the field would not fit in the maximum width, in
+/// which case they are wrapped and aligned after the opening brace. `at` is the column the type
+/// starts at.
+fn type_cell(state: &mut State, field: &FieldDecl, at: usize) -> Result<Vec<String>> {
+    let field_type = render(state, field.field_type)?;
+    if field.restrictions.is_empty() {
+        return Ok(vec![field_type]);
     }
 
-    state.formatted = saved_formatted;
-    state.arguments.inplace = saved_inplace;
+    let items = field
+        .restrictions
+        .iter()
+        .map(|node| render(state, *node))
+        .collect::<Result<Vec<_>>>()?;
+    let inline = format!("{}{{{}}}", field_type, items.join(", "));
+    let rest = render(state, field.name)?.len() + render(state, field.value)?.len() + 2;
+    if at + inline.len() + rest <= state.arguments.max_width {
+        return Ok(vec![inline]);
+    }
 
-    (kind_size, type_size, name_size, value_size)
+    let width = state.arguments.max_width.saturating_sub(at);
+    let continuation = " ".repeat(field_type.len() + 1);
+    let mut lines = Vec::new();
+    let mut line = format!("{}{{", field_type);
+    let mut empty = true;
+    for (i, item) in items.iter().enumerate() {
+        let piece = if i + 1 == items.len() {
+            format!("{}}}", item)
+        } else {
+            format!("{},", item)
+        };
+        if !empty && line.len() + 1 + piece.len() > width {
+            lines.push(line);
+            line = continuation.clone();
+            empty = true;
+        }
+        if !empty {
+            line += " ";
+        }
+        line += piece.as_str();
+        empty = false;
+    }
+    lines.push(line);
+    Ok(lines)
 }
 
 fn format_node_def(state: &mut State, node: Node) -> Result<()> {
@@ -388,13 +651,22 @@ fn format_node_def(state: &mut State, node: Node) -> Result<()> {
 
     let mut ok = false;
     let mut cursor = node.walk();
+    let mut children: Vec<Node> = node.children(&mut cursor).collect();
     let mut last_row = 0;
+    let template_level = state.template_level;
+    if state.arguments.canonical_order {
+        let node_type = NodeForm::new(node).and_then(|form| form.node_type());
+        if let Some(node_type) = node_type {
+            children = canonical_order(state, node_type.utf8_text(state.code)?, children);
+        }
+    }
 
     state.level += 1;
-    for child in node.children(&mut cursor) {
+    for child in children {
         match (child.kind(), ok) {
             ("{", false) => ok = true,
             ("}", true) => ok = false,
+            (_, true) if state.is_printed(child) => {}
             ("comment", true) => {
                 if !oneliner && last_row != child.range().start_point.row {
                     state.println("");
@@ -405,6 +677,7 @@ fn format_node_def(state: &mut State, node: Node) -> Result<()> {
                 format_comment(state, child)?;
             }
             (_, true) => {
+                state.close_template(child);
                 if !oneliner {
                     state.println("");
                     state.indent();
@@ -418,6 +691,7 @@ fn format_node_def(state: &mut State, node: Node) -> Result<()> {
         last_row = child.range().end_point.row;
     }
     state.level -= 1;
+    state.template_level = template_level;
 
     if oneliner {
         state.print(" ");
@@ -429,6 +703,62 @@ fn format_node_def(state: &mut State, node: Node) -> Result<()> {
     Ok(())
 }
 
+/// The children of a built-in node with its fields in schema order, as Webots saves them. Fields
+/// keep the comments right above them and the one trailing them, and unknown fields go last.
+/// Bodies with template code or formatter directives are left as they are.
+fn canonical_order<'a>(state: &State, node_type: &str, children: Vec<Node<'a>>) -> Vec<Node<'a>> {
+    let Some(fields) = node_fields(node_type, state.release) else {
+        return children;
+    };
+    let fixed = children.iter().any(|child| match child.kind() {
+        "javascript_block" | "javascript_expression" => true,
+        "comment" => directive(child.utf8_text(state.code).unwrap_or_default()).is_some(),
+        _ => false,
+    });
+    let open = children.iter().position(|child| child.kind() == "{");
+    let close = children.iter().rposition(|child| child.kind() == "}");
+    let (Some(open), Some(close), false) = (open, close, fixed) else {
+        return children;
+    };
+
+    let mut groups: Vec<Vec<Node>> = Vec::new();
+    let mut pending = Vec::new();
+    for &child in &children[open + 1..close] {
+        if child.kind() == "comment" && pending.is_empty() {
+            if let Some(group) = groups.last_mut() {
+                let trailing = group
+                    .last()
+                    .is_some_and(|last| last.end_position().row == child.start_position().row);
+                if trailing {
+                    group.push(child);
+                    continue;
+                }
+            }
+        }
+        pending.push(child);
+        if child.kind() != "comment" {
+            groups.push(std::mem::take(&mut pending));
+        }
+    }
+
+    let position = |group: &Vec<Node>| {
+        let name = group
+            .iter()
+            .find(|child| child.kind() == "property")
+            .and_then(|property| property.child(0))
+            .and_then(|name| name.utf8_text(state.code).ok());
+        name.and_then(|name| fields.iter().position(|field| field.name == name))
+            .unwrap_or(usize::MAX)
+    };
+    groups.sort_by_key(position);
+
+    let mut ordered = children[..=open].to_vec();
+    ordered.extend(groups.into_iter().flatten());
+    ordered.extend(pending);
+    ordered.extend_from_slice(&children[close..]);
+    ordered
+}
+
 fn format_property(state: &mut State, node: Node) -> Result<()> {
     let mut first = true;
     let mut cursor = node.walk();
@@ -447,8 +777,10 @@ fn format_vector(state: &mut State, node: Node) -> Result<()> {
     let mut cursor = node.walk();
     let mut last_node = node;
     let mut brackets = false;
+    let template_level = state.template_level;
     for child in node.children(&mut cursor) {
         match child.kind() {
+            _ if state.is_printed(child) => {}
             "[" => {
                 state.print("[");
                 if !oneliner {
@@ -459,6 +791,7 @@ fn format_vector(state: &mut State, node: Node) -> Result<()> {
                 continue;
             }
             "]" => {
+                state.template_level = template_level;
                 if oneliner {
                     state.print(" ]");
                 } else {
@@ -483,6 +816,7 @@ fn format_vector(state: &mut State, node: Node) -> Result<()> {
                 format_comment(state, child)?;
             }
             _ => {
+                state.close_template(child);
                 if oneliner && (brackets || last_node != node) {
                     state.print(" ");
                 } else if !oneliner {
@@ -546,5 +880,388 @@ fn format_javascript(state: &mut State, node: Node) -> Result<()> {
         state.indent();
         state.print(">%");
     }
+
+    if let Some((_, opens)) = state.template_braces(node) {
+        state.template_level += opens;
+    }
     Ok(())
 }
+
+/// Counts the unmatched braces of JavaScript code: the closing ones, then the opening ones.
+/// Braces in strings and comments do not count.
+fn braces(code: &str) -> (usize, usize) {
+    let (mut closes, mut opens) = (0, 0);
+    let mut chars = code.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => opens += 1,
+            '}' if opens > 0 => opens -= 1,
+            '}' => closes += 1,
+            '"' | '\'' | '`' => {
+                while let Some(next) = chars.next() {
+                    match next {
+                        '\\' => {
+                            chars.next();
+                        }
+                        _ if next == c => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|&next| next == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                for next in chars.by_ref() {
+                    if last == '*' && next == '/' {
+                        break;
+                    }
+                    last = next;
+                }
+            }
+            _ => {}
+        }
+    }
+    (closes, opens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::Parser;
+
+    fn format(code: &str, flags: &[&str]) -> String {
+        let args = ["wbproto-beautifier", "--inplace"].iter().chain(flags);
+        let mut arguments = Arguments::parse_from(args);
+        beautify(code, &mut arguments).unwrap()
+    }
+
+    /// Whether some line of `text` has these words, regardless of the spacing between them.
+    fn has_line(text: &str, words: &[&str]) -> bool {
+        text.lines()
+            .any(|line| line.split_whitespace().eq(words.iter().copied()))
+    }
+
+    #[test]
+    fn header_is_printed_normalized() {
+        let code = r#"#VRML_SIM   R2023b utf8
+#  tags: hidden
+# license:MIT
+#A wall.
+PROTO Wall [
+]
+{
+  Solid {
+  }
+}
+"#;
+        let formatted = format(code, &[]);
+        assert!(
+            formatted.starts_with(
+                "#VRML_SIM R2023b utf8\n# license: MIT\n# tags: hidden\n# A wall.\n\nPROTO Wall ["
+            ),
+            "{formatted}"
+        );
+        assert_eq!(format(&formatted, &[]), formatted);
+    }
+
+    #[test]
+    fn restrictions_are_kept_inline_when_they_fit() {
+        let code = r#"#VRML_SIM R2023b utf8
+PROTO Lamp [
+  field SFString{"low","high"}   quality "low"
+]
+{
+  Solid {
+  }
+}
+"#;
+        let formatted = format(code, &[]);
+        assert!(
+            formatted.contains("\n  field  SFString{\"low\", \"high\"}  quality  \"low\"\n"),
+            "{formatted}"
+        );
+        assert_eq!(format(&formatted, &[]), formatted);
+    }
+
+    #[test]
+    fn restrictions_wrap_aligned_after_the_brace() {
+        let code = r#"#VRML_SIM R2023b utf8
+PROTO Lamp [
+  field SFString { "alpha", "bravo", "charlie", "delta" } mode "alpha"
+]
+{
+  Solid {
+  }
+}
+"#;
+        let formatted = format(code, &["--max-width", "40"]);
+        let expected = [
+            "  field  SFString{\"alpha\", \"bravo\",",
+            "                  \"charlie\", \"delta\"}  mode  \"alpha\"",
+        ]
+        .join("\n");
+        assert!(formatted.contains(&expected), "{formatted}");
+        assert_eq!(format(&formatted, &["--max-width", "40"]), formatted);
+    }
+
+    #[test]
+    fn reflow_rewraps_paragraphs() {
+        assert_eq!(
+            reflow(&["# aaa bbb ccc", "#   ddd"], 12),
+            ["# aaa bbb", "# ccc ddd"]
+        );
+        assert_eq!(reflow(&["# a", "#", "# b"], 80), ["# a", "#", "# b"]);
+        assert_eq!(reflow(&["# a", "", "# b"], 80), ["# a", "", "# b"]);
+    }
+
+    #[test]
+    fn reflow_keeps_banners_lists_and_keys() {
+        let lines = [
+            "## Title ##",
+            "# - first item",
+            "# 2) second item",
+            "# license: MIT",
+            "# some",
+            "# words",
+        ];
+        assert_eq!(
+            reflow(&lines, 80),
+            [
+                "## Title ##",
+                "# - first item",
+                "# 2) second item",
+                "# license: MIT",
+                "# some words",
+            ]
+        );
+    }
+
+    #[test]
+    fn comment_blocks_are_wrapped_at_their_indentation() {
+        let code = r#"#VRML_SIM R2023b utf8
+Solid {
+  # one two three four five six seven eight nine ten
+  name "x"
+}
+"#;
+        let flags = ["--wrap-comments", "--max-width", "30"];
+        let formatted = format(code, &flags);
+        let expected = "  # one two three four five\n  # six seven eight nine ten\n  name \"x\"\n";
+        assert!(formatted.contains(expected), "{formatted}");
+        assert_eq!(format(&formatted, &flags), formatted);
+        assert!(
+            format(code, &[]).contains("  # one two three four five six seven eight nine ten\n")
+        );
+    }
+
+    #[test]
+    fn off_region_of_interface_fields_is_printed_once() {
+        let table = "  field MFFloat ratios [ 1.0  2.5
+                         4.0  8.0 ]";
+        let code = format!(
+            r#"#VRML_SIM R2023b utf8
+PROTO Gears [
+  # wbproto-beautifier: off
+{table}
+  # wbproto-beautifier: on
+  field SFFloat   speed 1
+]
+{{
+  Solid {{
+  }}
+}}
+"#
+        );
+        let formatted = format(&code, &[]);
+        assert_eq!(formatted.matches(table).count(), 1, "{formatted}");
+        assert_eq!(formatted.matches("field").count(), 2, "{formatted}");
+        assert!(
+            has_line(&formatted, &["field", "SFFloat", "speed", "1"]),
+            "{formatted}"
+        );
+        assert_eq!(format(&formatted, &[]), formatted);
+    }
+
+    #[test]
+    fn ignore_next_interface_field_is_printed_once() {
+        let code = r#"#VRML_SIM R2023b utf8
+PROTO Table [
+  # wbproto-beautifier: ignore-next
+  field   SFFloat   x   1
+  field SFFloat y 2
+]
+{
+  Solid {
+  }
+}
+"#;
+        let formatted = format(code, &[]);
+        assert_eq!(
+            formatted.matches("  field   SFFloat   x   1\n").count(),
+            1,
+            "{formatted}"
+        );
+        assert_eq!(formatted.matches("field").count(), 2, "{formatted}");
+        assert_eq!(format(&formatted, &[]), formatted);
+    }
+
+    #[test]
+    fn off_region_of_vector_keeps_its_commas() {
+        let table = "    0 0 0,   1 0 0,
+    0 1 0,   1 1 0,";
+        let code = format!(
+            r#"#VRML_SIM R2023b utf8
+Coordinate {{
+  point [
+    # wbproto-beautifier: off
+{table}
+    # wbproto-beautifier: on
+    0 0 1
+  ]
+}}
+"#
+        );
+        let formatted = format(&code, &[]);
+        assert_eq!(formatted.matches(table).count(), 1, "{formatted}");
+        assert_eq!(formatted.matches(',').count(), 4, "{formatted}");
+        assert_eq!(format(&formatted, &[]), formatted);
+    }
+
+    #[test]
+    fn unmatched_braces_of_template_code() {
+        assert_eq!(braces("for (let i = 0; i < 2; ++i) {"), (0, 1));
+        assert_eq!(braces("}"), (1, 0));
+        assert_eq!(braces("} else if (x) {"), (1, 1));
+        assert_eq!(braces("}}"), (2, 0));
+        assert_eq!(braces("const o = { a: 1 };"), (0, 0));
+        assert_eq!(
+            braces("const s = '{' + \"}\" + `{`; // {\n/* } */ f({ a: '\\'' })"),
+            (0, 0)
+        );
+    }
+
+    const TEMPLATE: &str = r#"#VRML_SIM R2023b utf8
+# template language: javascript
+
+PROTO Boxes [
+]
+{
+  Group {
+    children [
+      %< for (let i = 0; i < 2; ++i) { >%
+      %< if (i > 0) { >%
+      Solid {
+        name "a"
+      }
+      %< } else { >%
+      Pose {
+      }
+      %< } >%
+      %< } >%
+    ]
+  }
+}
+"#;
+
+    #[test]
+    fn template_braces_are_indented_as_nesting_levels() {
+        let formatted = format(TEMPLATE, &["--template-indent"]);
+        let expected = r#"    children [
+      %< for (let i = 0; i < 2; ++i) { >%
+        %< if (i > 0) { >%
+          Solid {
+            name "a"
+          }
+        %< } else { >%
+          Pose {
+          }
+        %< } >%
+      %< } >%
+    ]
+  }
+}
+"#;
+        assert!(formatted.ends_with(expected), "{formatted}");
+        assert_eq!(format(&formatted, &["--template-indent"]), formatted);
+    }
+
+    #[test]
+    fn template_braces_are_not_indented_by_default() {
+        let formatted = format(TEMPLATE, &[]);
+        assert!(formatted.contains("\n      Solid {\n"), "{formatted}");
+        let indented = format(&format(TEMPLATE, &["--template-indent"]), &[]);
+        assert_eq!(indented, formatted);
+    }
+
+    /// The rows of the lines of `text` starting with each of `starts`, after their indentation.
+    fn rows(text: &str, starts: &[&str]) -> Vec<Option<usize>> {
+        starts
+            .iter()
+            .map(|start| {
+                text.lines()
+                    .position(|line| line.trim_start().starts_with(start))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn canonical_order_sorts_fields_in_schema_order() {
+        let code = r#"#VRML_SIM R2023b utf8
+Solid {
+  # The physics.
+  physics Physics {
+  }
+  name "box"  # Its name.
+  unknownThing 1
+  translation 0 0 1
+}
+"#;
+        let formatted = format(code, &["--canonical-order"]);
+        let order = rows(
+            &formatted,
+            &[
+                "translation",
+                "name \"box\"",
+                "# The physics.",
+                "physics",
+                "unknownThing",
+            ],
+        );
+        assert!(order.iter().all(Option::is_some), "{formatted}");
+        assert!(order.windows(2).all(|w| w[0] < w[1]), "{formatted}");
+        assert!(has_line(
+            &formatted,
+            &["name", "\"box\"", "#", "Its", "name."]
+        ));
+        assert_eq!(format(&formatted, &["--canonical-order"]), formatted);
+
+        let unsorted = format(code, &[]);
+        let order = rows(&unsorted, &["physics", "name \"box\"", "translation"]);
+        assert!(order.windows(2).all(|w| w[0] < w[1]), "{unsorted}");
+    }
+
+    #[test]
+    fn canonical_order_keeps_unknown_nodes_and_directives() {
+        let code = r#"#VRML_SIM R2023b utf8
+Wall {
+  name "w"
+  size 1
+}
+Solid {
+  # wbproto-beautifier: ignore-next
+  name   "a"
+  translation 0 0 1
+}
+"#;
+        let formatted = format(code, &["--canonical-order"]);
+        assert_eq!(formatted, format(code, &[]));
+        let order = rows(
+            &formatted,
+            &["name \"w\"", "size", "name   \"a\"", "translation"],
+        );
+        assert!(order.windows(2).all(|w| w[0] < w[1]), "{formatted}");
+    }
+}
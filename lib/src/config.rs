@@ -0,0 +1,117 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use super::lint::Severity;
+
+/// Name of the configuration file looked up in the current directory and its parents.
+pub const CONFIG_FILE: &str = "wbproto.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Severity of each lint rule, by rule id.
+    pub rules: HashMap<String, Severity>,
+
+    pub externproto: ExternProtoConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ExternProtoConfig {
+    /// Local Webots installation or checkout `webots://` URLs are resolved against.
+    pub webots_home: Option<PathBuf>,
+
+    /// Directories standing for URL prefixes, such as
+    /// `"https://raw.githubusercontent.com/me/protos/main/" = "../protos/"`.
+    pub mappings: BTreeMap<String, PathBuf>,
+
+    /// Directories searched, recursively, for the PROTOs to declare when fixing missing
+    /// EXTERNPROTOs.
+    pub search_paths: Vec<PathBuf>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+        let mut config: Config =
+            toml::from_str(text.as_str()).with_context(|| format!("Invalid {}", path.display()))?;
+
+        // Paths in the file are relative to the file itself.
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let externproto = &mut config.externproto;
+        externproto.webots_home = externproto.webots_home.take().map(|home| dir.join(home));
+        for mapped in externproto.mappings.values_mut() {
+            *mapped = dir.join(&mapped);
+        }
+        for search_path in externproto.search_paths.iter_mut() {
+            *search_path = dir.join(&search_path);
+        }
+        Ok(config)
+    }
+
+    /// Loads the first configuration file found in `dir` or its parents, or the default
+    /// configuration if there is none.
+    pub fn discover(dir: &Path) -> Result<Config> {
+        match Config::find(dir) {
+            Some(path) => Config::load(path.as_path()),
+            None => Ok(Config::default()),
+        }
+    }
+
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|dir| dir.join(CONFIG_FILE))
+            .find(|path| path.is_file())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn load_resolves_paths_against_the_file() {
+        let dir = TempDir::new("config");
+        dir.write(
+            CONFIG_FILE,
+            r#"
+[rules]
+header = "error"
+
+[externproto]
+webots-home = "webots"
+search-paths = ["protos"]
+
+[externproto.mappings]
+"https://example.com/protos/" = "../shared"
+"#,
+        );
+
+        let config = Config::discover(&dir).unwrap();
+        assert_eq!(config.rules["header"], Severity::Error);
+        let externproto = &config.externproto;
+        assert_eq!(externproto.webots_home, Some(dir.join("webots")));
+        assert_eq!(externproto.search_paths, [dir.join("protos")]);
+        assert_eq!(
+            externproto.mappings["https://example.com/protos/"],
+            dir.join("../shared")
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("[rule]\nheader = \"off\"\n").is_err());
+        assert!(toml::from_str::<Config>("[rules]\nheader = \"loud\"\n").is_err());
+    }
+}
@@ -0,0 +1,352 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use tree_sitter::Node;
+
+use super::config::Config;
+use super::edit::Edit;
+use super::externproto::{externprotos, Resolver};
+use super::header::{Header, Release};
+use super::interface::{interface, protos};
+use super::schema::{default_change, field_schema};
+use super::syntax::{parse, parse_valid, text, NodeForm, Property};
+use super::types::{classify, Literal};
+
+/// A node field set to its default value.
+#[derive(Debug, Clone)]
+pub struct DefaultField {
+    /// The 0-based row of the field.
+    pub row: usize,
+    /// The node type and field, as in `Solid.rotation`.
+    pub name: String,
+    /// The deletion of the field, unless its default changed between Webots releases, in which
+    /// case the field is kept and the release of the change given instead.
+    pub removal: Result<Edit, Release>,
+}
+
+/// The fields of `code` set to the default value of the built-in node or the PROTO they belong to.
+/// PROTOs are looked up among the ones defined in `code` and the EXTERNPROTOs that resolve to local
+/// files. Fields bound with `IS`, holding nodes or template code, or followed by a comment on their
+/// line are left out.
+pub fn default_fields(
+    code: &str,
+    path: Option<&Path>,
+    config: &Config,
+) -> Result<Vec<DefaultField>> {
+    let tree = parse_valid(code)?;
+    let root = tree.root_node();
+    let release = Header::from_source(code).release();
+
+    let mut proto_defaults: HashMap<String, HashMap<String, String>> = HashMap::new();
+    for proto in protos(root) {
+        if let Some(name) = proto.child_by_field_name("proto") {
+            proto_defaults.insert(
+                text(name, code).to_string(),
+                interface_defaults(proto, code),
+            );
+        }
+    }
+    let resolver = Resolver::new(&config.externproto);
+    for externproto in externprotos(root, code) {
+        let Ok(file) = resolver.resolve(externproto.url, path) else {
+            continue;
+        };
+        let Ok(source) = std::fs::read_to_string(file) else {
+            continue;
+        };
+        let Ok(tree) = parse(source.as_str()) else {
+            continue;
+        };
+        if let Some(proto) = protos(tree.root_node()).first() {
+            let defaults = interface_defaults(*proto, source.as_str());
+            proto_defaults.insert(externproto.name().to_string(), defaults);
+        }
+    }
+
+    let mut fields = Vec::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor));
+        if node.kind() != "property" {
+            continue;
+        }
+        let Some(property) = Property::new(node) else {
+            continue;
+        };
+        let Some(node_type) = node
+            .parent()
+            .filter(|parent| parent.kind() == "node")
+            .and_then(NodeForm::new)
+            .and_then(|form| form.node_type())
+        else {
+            continue;
+        };
+        let trailing_comment = node.next_sibling().is_some_and(|next| {
+            next.kind() == "comment" && next.start_position().row == node.end_position().row
+        });
+        let plain = property
+            .values
+            .iter()
+            .all(|value| !matches!(classify(*value, code), Literal::Node | Literal::Template));
+        if property.is_target.is_some() || property.values.is_empty() || !plain || trailing_comment
+        {
+            continue;
+        }
+
+        let node_type = text(node_type, code);
+        let field = text(property.name, code);
+        let default = match field_schema(node_type, field, release) {
+            Some(schema) => schema.default.map(str::to_string),
+            None => proto_defaults
+                .get(node_type)
+                .and_then(|defaults| defaults.get(field))
+                .cloned(),
+        };
+        let value =
+            &code[property.values[0].start_byte()..property.values.last().unwrap().end_byte()];
+        if !default.is_some_and(|default| same_value(value, default.as_str())) {
+            continue;
+        }
+
+        let removal = match default_change(node_type, field) {
+            Some(release) => Err(release),
+            None => Ok(Edit::delete_line(code, node.start_byte(), node.end_byte())),
+        };
+        fields.push(DefaultField {
+            row: node.start_position().row,
+            name: format!("{node_type}.{field}"),
+            removal,
+        });
+    }
+    fields.sort_by_key(|field| field.row);
+    Ok(fields)
+}
+
+/// The default values of the interface of a PROTO, leaving out the ones with template code.
+fn interface_defaults(proto: Node, code: &str) -> HashMap<String, String> {
+    interface(proto)
+        .into_iter()
+        .filter(|field| !has_template(field.value))
+        .map(|field| {
+            (
+                text(field.name, code).to_string(),
+                text(field.value, code).to_string(),
+            )
+        })
+        .collect()
+}
+
+fn has_template(node: Node) -> bool {
+    if matches!(node.kind(), "javascript_block" | "javascript_expression") {
+        return true;
+    }
+    let mut cursor = node.walk();
+    let has = node.children(&mut cursor).any(has_template);
+    has
+}
+
+/// Whether two values are the same, regardless of brackets, commas, spacing and the way numbers
+/// are written.
+pub fn same_value(a: &str, b: &str) -> bool {
+    let (a, b) = (tokens(a), tokens(b));
+    a.len() == b.len()
+        && a.iter().zip(&b).all(|(a, b)| {
+            a == b
+                || a.parse::<f64>()
+                    .is_ok_and(|x| b.parse::<f64>().is_ok_and(|y| x == y))
+        })
+}
+
+/// The literals of a value: strings with their quotes, and words separated by spaces, commas and
+/// brackets.
+pub fn tokens(value: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+                tokens.push(&value[start.take().unwrap_or(i)..=i]);
+            }
+        } else if c == '"' {
+            in_string = true;
+            start = Some(i);
+        } else if c.is_whitespace() || matches!(c, ',' | '[' | ']') {
+            if let Some(start) = start.take() {
+                tokens.push(&value[start..i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(start) = start {
+        tokens.push(&value[start..]);
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit::apply_edits;
+    use crate::testing::TempDir;
+
+    /// The fields of `code` set to their default, as `(line, name, removed)`, and `code` without
+    /// the removed ones.
+    fn strip(code: &str, path: Option<&Path>) -> (Vec<(usize, String, bool)>, String) {
+        let fields = default_fields(code, path, &Config::default()).unwrap();
+        let edits: Vec<Edit> = fields
+            .iter()
+            .filter_map(|field| field.removal.clone().ok())
+            .collect();
+        let found = fields
+            .into_iter()
+            .map(|field| (field.row + 1, field.name, field.removal.is_ok()))
+            .collect();
+        (found, apply_edits(code, &edits))
+    }
+
+    #[test]
+    fn built_in_defaults_are_removed() {
+        let code = r#"#VRML_SIM R2023b utf8
+Solid {
+  translation 0.0 0 0
+  children [
+    Shape {
+      castShadows TRUE
+      isPickable FALSE
+    }
+  ]
+  name "solid"
+  locked FALSE
+}
+"#;
+        let (found, stripped) = strip(code, None);
+        assert_eq!(
+            found,
+            [
+                (3, "Solid.translation".to_string(), true),
+                (6, "Shape.castShadows".to_string(), true),
+                (10, "Solid.name".to_string(), true),
+                (11, "Solid.locked".to_string(), true),
+            ]
+        );
+        assert_eq!(
+            stripped,
+            r#"#VRML_SIM R2023b utf8
+Solid {
+  children [
+    Shape {
+      isPickable FALSE
+    }
+  ]
+}
+"#
+        );
+    }
+
+    #[test]
+    fn changed_defaults_are_kept() {
+        let code = r#"#VRML_SIM R2023b utf8
+Solid {
+  rotation 0 0 1 0
+}
+"#;
+        let fields = default_fields(code, None, &Config::default()).unwrap();
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].name, "Solid.rotation");
+        assert_eq!(fields[0].removal, Err(Release::parse("R2022a").unwrap()));
+    }
+
+    #[test]
+    fn bound_commented_node_and_template_fields_are_kept() {
+        let code = r#"#VRML_SIM R2023b utf8
+PROTO Box [
+  field SFString name "solid"
+]
+{
+  Solid {
+    name IS name
+    translation 0 0 0  # On the floor.
+    physics NULL
+    rotation %<= "0 0 1 0" >%
+    boundingObject Box {
+    }
+  }
+}
+"#;
+        let (found, stripped) = strip(code, None);
+        assert_eq!(found, [(9, "Solid.physics".to_string(), true)]);
+        assert!(!stripped.contains("physics"));
+        assert!(stripped.contains("translation 0 0 0  # On the floor."));
+    }
+
+    #[test]
+    fn proto_interface_defaults_are_removed() {
+        let dir = TempDir::new("defaults");
+        dir.write(
+            "Lamp.proto",
+            r#"#VRML_SIM R2023b utf8
+PROTO Lamp [
+  field SFFloat power 1
+  field MFColor colors [ 1 1 1, 0 0 0 ]
+  field SFString label "lamp"
+]
+{
+  Solid {
+  }
+}
+"#,
+        );
+        let code = r#"#VRML_SIM R2023b utf8
+EXTERNPROTO "Lamp.proto"
+Lamp {
+  power 1.0
+  colors [ 1 1 1 0 0 0 ]
+  label "light"
+}
+"#;
+        let world = dir.join("world.wbt");
+        let (found, stripped) = strip(code, Some(&world));
+        assert_eq!(
+            found,
+            [
+                (4, "Lamp.power".to_string(), true),
+                (5, "Lamp.colors".to_string(), true),
+            ]
+        );
+        assert!(stripped.contains("label \"light\""));
+        assert_eq!(strip(code, None).0, []);
+    }
+
+    #[test]
+    fn values_compare_regardless_of_writing() {
+        assert!(same_value("0 0 1 0", "0.0 0 1.0 -0"));
+        assert!(same_value("[ 1 1 1, 0 0 0 ]", "[1 1 1 0 0 0]"));
+        assert!(same_value("[]", ""));
+        assert!(!same_value("\"a b\"", "\"a\" \"b\""));
+        assert!(!same_value("1 2", "1 2 3"));
+        assert!(!same_value("TRUE", "FALSE"));
+    }
+
+    #[test]
+    fn tokens_of_values() {
+        assert_eq!(tokens(r#"[ "a, b" "c\"d" ]"#), [r#""a, b""#, r#""c\"d""#]);
+        assert_eq!(tokens("1,2 [3]"), ["1", "2", "3"]);
+        assert_eq!(tokens(""), Vec::<&str>::new());
+    }
+}
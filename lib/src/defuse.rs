@@ -0,0 +1,254 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::{HashMap, HashSet};
+
+use tree_sitter::Node;
+
+use super::lint::{Context, Report, Rule, Severity};
+use super::syntax::NodeForm;
+
+/// A `DEF` or `USE` name and the identifier node holding it.
+#[derive(Debug, Clone, Copy)]
+pub struct Binding<'a> {
+    pub name: &'a str,
+    pub identifier: Node<'a>,
+}
+
+/// A DEF namespace: the world or PROTO file itself, a PROTO body or the default value of a PROTO
+/// interface field. Definitions and uses are kept in document order.
+#[derive(Debug, Clone)]
+pub struct Scope<'a> {
+    pub node: Node<'a>,
+    pub defs: Vec<Binding<'a>>,
+    pub uses: Vec<Binding<'a>>,
+}
+
+/// Collects the DEF and USE sites of every scope in the tree under `root`.
+pub fn scopes<'a>(root: Node<'a>, code: &'a str) -> Vec<Scope<'a>> {
+    let mut scopes = vec![Scope {
+        node: root,
+        defs: Vec::new(),
+        uses: Vec::new(),
+    }];
+    collect(root, 0, code, &mut scopes);
+    scopes
+}
+
+fn collect<'a>(node: Node<'a>, scope: usize, code: &'a str, scopes: &mut Vec<Scope<'a>>) {
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        let scope = match child.kind() {
+            "proto" | "field" => {
+                scopes.push(Scope {
+                    node: child,
+                    defs: Vec::new(),
+                    uses: Vec::new(),
+                });
+                scopes.len() - 1
+            }
+            "node" => {
+                let binding = |identifier: Node<'a>| Binding {
+                    name: identifier.utf8_text(code.as_bytes()).unwrap_or_default(),
+                    identifier,
+                };
+                match NodeForm::new(child) {
+                    Some(NodeForm::Def { name, .. }) => scopes[scope].defs.push(binding(name)),
+                    Some(NodeForm::Use { name }) => scopes[scope].uses.push(binding(name)),
+                    _ => {}
+                }
+                scope
+            }
+            _ => scope,
+        };
+        collect(child, scope, code, scopes);
+    }
+}
+
+/// `USE X` appearing before any `DEF X` of its scope.
+pub struct UseBeforeDef;
+
+impl Rule for UseBeforeDef {
+    fn id(&self) -> &'static str {
+        "use-before-def"
+    }
+
+    fn description(&self) -> &'static str {
+        "USE of a name that is not DEFined before it in the same scope"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, context: &Context, report: &mut Report) {
+        for scope in scopes(context.root, context.code) {
+            for usage in &scope.uses {
+                let start = usage.identifier.start_byte();
+                let mut defs = scope.defs.iter().filter(|def| def.name == usage.name);
+                if defs.clone().any(|def| def.identifier.start_byte() < start) {
+                    continue;
+                }
+                match defs.next() {
+                    Some(def) => {
+                        let message = format!("USE {} before its DEF", usage.name);
+                        report
+                            .add(usage.identifier.range(), message)
+                            .related
+                            .push((def.identifier.range(), format!("DEF {} is here", def.name)));
+                    }
+                    None => {
+                        let message = format!("USE of undefined name {}", usage.name);
+                        report.add(usage.identifier.range(), message);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The same name `DEF`ined twice in a scope.
+pub struct DuplicateDef;
+
+impl Rule for DuplicateDef {
+    fn id(&self) -> &'static str {
+        "duplicate-def"
+    }
+
+    fn description(&self) -> &'static str {
+        "DEF name already defined in the same scope"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, context: &Context, report: &mut Report) {
+        for scope in scopes(context.root, context.code) {
+            let mut seen: HashMap<&str, Node> = HashMap::new();
+            for def in &scope.defs {
+                match seen.get(def.name) {
+                    Some(first) => {
+                        let message = format!("DEF {} is already defined", def.name);
+                        report
+                            .add(def.identifier.range(), message)
+                            .related
+                            .push((first.range(), "first defined here".to_string()));
+                    }
+                    None => {
+                        seen.insert(def.name, def.identifier);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A `DEF` name no `USE` refers to.
+pub struct UnusedDef;
+
+impl Rule for UnusedDef {
+    fn id(&self) -> &'static str {
+        "unused-def"
+    }
+
+    fn description(&self) -> &'static str {
+        "DEF name that is never USEd (it may still be looked up by a supervisor)"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Info
+    }
+
+    fn check(&self, context: &Context, report: &mut Report) {
+        for scope in scopes(context.root, context.code) {
+            let used: HashSet<&str> = scope.uses.iter().map(|usage| usage.name).collect();
+            for def in scope.defs.iter().filter(|def| !used.contains(def.name)) {
+                report.add(
+                    def.identifier.range(),
+                    format!("DEF {} is never used", def.name),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lint::tests::{check, messages};
+
+    const WORLD: &str = r#"#VRML_SIM R2023b utf8
+Solid {
+  children [
+    USE WHEEL
+    DEF WHEEL Shape {
+    }
+    USE WHEEL
+    USE GHOST
+    DEF WHEEL Shape {
+    }
+    DEF LONELY Shape {
+    }
+  ]
+}
+"#;
+
+    #[test]
+    fn use_before_def() {
+        assert_eq!(
+            messages("use-before-def", WORLD),
+            [
+                (4, "USE WHEEL before its DEF".to_string()),
+                (8, "USE of undefined name GHOST".to_string()),
+            ]
+        );
+        let related = &check("use-before-def", WORLD)[0].related;
+        assert_eq!(related[0].0.start_point.row + 1, 5);
+    }
+
+    #[test]
+    fn duplicate_def() {
+        assert_eq!(
+            messages("duplicate-def", WORLD),
+            [(9, "DEF WHEEL is already defined".to_string())]
+        );
+    }
+
+    #[test]
+    fn unused_def() {
+        assert_eq!(
+            messages("unused-def", WORLD),
+            [(11, "DEF LONELY is never used".to_string())]
+        );
+    }
+
+    #[test]
+    fn proto_bodies_and_field_defaults_are_separate_scopes() {
+        let code = r#"#VRML_SIM R2023b utf8
+PROTO Box [
+  field SFNode appearance DEF LOOK Appearance {
+  }
+]
+{
+  Solid {
+    children [
+      Shape {
+        appearance USE LOOK
+      }
+    ]
+  }
+}
+"#;
+        assert_eq!(
+            messages("use-before-def", code),
+            [(10, "USE of undefined name LOOK".to_string())]
+        );
+        assert_eq!(
+            messages("unused-def", code),
+            [(3, "DEF LOOK is never used".to_string())]
+        );
+    }
+}
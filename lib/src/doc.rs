@@ -0,0 +1,350 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use anyhow::{anyhow, Result};
+use tree_sitter::Node;
+
+use super::args::DocFormat;
+use super::header::Header;
+use super::interface::protos;
+use super::syntax::{parse_valid, text, FieldDecl};
+
+/// An interface field, as documented.
+#[derive(Debug, Clone)]
+struct FieldDoc {
+    kind: String,
+    field_type: String,
+    name: String,
+    default: String,
+    restrictions: Vec<String>,
+    description: String,
+}
+
+/// Writes the reference documentation of the PROTOs in `code`: their name, the description, tags
+/// and license of the header, and a table of their interface fields, described by the comment
+/// trailing them or, failing that, by the comments right above them.
+pub fn documentation(code: &str, format: DocFormat) -> Result<String> {
+    let tree = parse_valid(code)?;
+    let root = tree.root_node();
+    let protos = protos(root);
+    if protos.is_empty() {
+        return Err(anyhow!("No PROTO to document."));
+    }
+
+    let header = Header::from_source(code);
+    let mut documentation = String::new();
+    for proto in protos {
+        let name = proto
+            .child_by_field_name("proto")
+            .map(|name| text(name, code))
+            .unwrap_or_default();
+        let fields = field_docs(proto, code)?;
+        match format {
+            DocFormat::Markdown => markdown(&mut documentation, name, &header, &fields),
+            DocFormat::Html => html(&mut documentation, name, &header, &fields),
+        }
+    }
+    Ok(documentation)
+}
+
+fn field_docs(proto: Node, code: &str) -> Result<Vec<FieldDoc>> {
+    let text = |node: Node| {
+        let text = node.utf8_text(code.as_bytes()).unwrap_or_default();
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    };
+
+    let mut fields: Vec<FieldDoc> = Vec::new();
+    // The full-line comments right above the next field, and the row of the last one.
+    let mut above: Vec<String> = Vec::new();
+    let mut above_row = None;
+    // The row the last field ends on, taking the comment trailing it.
+    let mut last_row = None;
+    let mut cursor = proto.walk();
+    for child in proto
+        .children(&mut cursor)
+        .skip_while(|child| child.kind() != "[")
+        .take_while(|child| child.kind() != "]")
+    {
+        match child.kind() {
+            "comment" => {
+                let comment = comment_text(text(child).as_str());
+                if comment.is_empty() || comment.starts_with("wbproto-beautifier:") {
+                    above.clear();
+                    above_row = None;
+                    continue;
+                }
+                let row = child.start_position().row;
+                if last_row == Some(row) {
+                    if let Some(field) = fields.last_mut() {
+                        field.description = comment;
+                    }
+                    continue;
+                }
+                if above_row.is_none_or(|above_row| above_row + 1 != row) {
+                    above.clear();
+                }
+                above.push(comment);
+                above_row = Some(row);
+            }
+            "field" => {
+                let field = FieldDecl::new(child)?;
+                let row = child.start_position().row;
+                let description = if above_row.is_some_and(|above_row| above_row + 1 == row) {
+                    above.join(" ")
+                } else {
+                    String::new()
+                };
+                above.clear();
+                above_row = None;
+                last_row = Some(child.end_position().row);
+                fields.push(FieldDoc {
+                    kind: text(field.kind),
+                    field_type: text(field.field_type),
+                    name: text(field.name),
+                    default: text(field.value),
+                    restrictions: field.restrictions.iter().map(|node| text(*node)).collect(),
+                    description,
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(fields)
+}
+
+fn comment_text(comment: &str) -> String {
+    comment.trim_start_matches('#').trim().to_string()
+}
+
+/// The paragraphs of the header description, without the banner naming the PROTO, as in
+/// `## Wall ##`, which the title already gives.
+fn paragraphs(header: &Header, name: &str) -> Vec<String> {
+    let mut paragraphs = header.paragraphs();
+    paragraphs.retain(|paragraph| paragraph != name);
+    paragraphs
+}
+
+fn markdown(out: &mut String, name: &str, header: &Header, fields: &[FieldDoc]) {
+    *out += format!("# {name}\n\n").as_str();
+    for paragraph in paragraphs(header, name) {
+        *out += format!("{paragraph}\n\n").as_str();
+    }
+
+    let mut facts = Vec::new();
+    if !header.tags.is_empty() {
+        facts.push(format!("**Tags:** {}", header.tags.join(", ")));
+    }
+    match (&header.license, &header.license_url) {
+        (Some(license), Some(url)) => facts.push(format!("**License:** [{license}]({url})")),
+        (Some(license), None) => facts.push(format!("**License:** {license}")),
+        (None, Some(url)) => facts.push(format!("**License:** <{url}>")),
+        (None, None) => {}
+    }
+    if let Some(url) = &header.documentation_url {
+        facts.push(format!("**Documentation:** <{url}>"));
+    }
+    if !facts.is_empty() {
+        *out += facts.join("  \n").as_str();
+        *out += "\n\n";
+    }
+
+    *out += "## Fields\n\n";
+    if fields.is_empty() {
+        *out += "This PROTO has no fields.\n\n";
+        return;
+    }
+    *out += "| Kind | Type | Name | Default | Restrictions | Description |\n";
+    *out += "| --- | --- | --- | --- | --- | --- |\n";
+    for field in fields {
+        let restrictions: Vec<String> = field
+            .restrictions
+            .iter()
+            .map(|restriction| code_span(restriction))
+            .collect();
+        let cells = [
+            field.kind.clone(),
+            field.field_type.clone(),
+            code_span(field.name.as_str()),
+            code_span(field.default.as_str()),
+            restrictions.join(", "),
+            field.description.clone(),
+        ];
+        let cells: Vec<String> = cells.iter().map(|cell| cell.replace('|', "\\|")).collect();
+        *out += format!("| {} |\n", cells.join(" | ")).as_str();
+    }
+    *out += "\n";
+}
+
+fn code_span(text: &str) -> String {
+    if text.contains('`') {
+        format!("`` {text} ``")
+    } else {
+        format!("`{text}`")
+    }
+}
+
+fn html(out: &mut String, name: &str, header: &Header, fields: &[FieldDoc]) {
+    *out += format!("<h1>{}</h1>\n", escape(name)).as_str();
+    for paragraph in paragraphs(header, name) {
+        *out += format!("<p>{}</p>\n", escape(paragraph.as_str())).as_str();
+    }
+
+    let mut facts = Vec::new();
+    if !header.tags.is_empty() {
+        facts.push(("Tags", escape(header.tags.join(", ").as_str())));
+    }
+    let license = match (&header.license, &header.license_url) {
+        (Some(license), Some(url)) => Some(format!(
+            "<a href=\"{}\">{}</a>",
+            escape(url),
+            escape(license)
+        )),
+        (Some(license), None) => Some(escape(license)),
+        (None, Some(url)) => Some(format!("<a href=\"{0}\">{0}</a>", escape(url))),
+        (None, None) => None,
+    };
+    if let Some(license) = license {
+        facts.push(("License", license));
+    }
+    if let Some(url) = &header.documentation_url {
+        facts.push((
+            "Documentation",
+            format!("<a href=\"{0}\">{0}</a>", escape(url)),
+        ));
+    }
+    if !facts.is_empty() {
+        *out += "<dl>\n";
+        for (term, definition) in facts {
+            *out += format!("  <dt>{term}</dt><dd>{definition}</dd>\n").as_str();
+        }
+        *out += "</dl>\n";
+    }
+
+    *out += "<h2>Fields</h2>\n";
+    if fields.is_empty() {
+        *out += "<p>This PROTO has no fields.</p>\n";
+        return;
+    }
+    *out += "<table>\n";
+    *out += "  <thead>\n";
+    *out += "    <tr><th>Kind</th><th>Type</th><th>Name</th><th>Default</th><th>Restrictions</th><th>Description</th></tr>\n";
+    *out += "  </thead>\n";
+    *out += "  <tbody>\n";
+    for field in fields {
+        let restrictions: Vec<String> = field
+            .restrictions
+            .iter()
+            .map(|restriction| format!("<code>{}</code>", escape(restriction)))
+            .collect();
+        *out += format!(
+            "    <tr><td>{}</td><td>{}</td><td><code>{}</code></td><td><code>{}</code></td><td>{}</td><td>{}</td></tr>\n",
+            escape(field.kind.as_str()),
+            escape(field.field_type.as_str()),
+            escape(field.name.as_str()),
+            escape(field.default.as_str()),
+            restrictions.join(", "),
+            escape(field.description.as_str()),
+        )
+        .as_str();
+    }
+    *out += "  </tbody>\n";
+    *out += "</table>\n";
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALL: &str = r#"#VRML_SIM R2023b utf8
+# license: Apache License 2.0
+# license url: https://www.apache.org/licenses/LICENSE-2.0
+# tags: nonDeterministic
+## Wall ##
+# A wall
+# made of bricks.
+
+PROTO Wall [
+  field SFVec3f size 1 1 1       # Size of the wall, in meters.
+  # Texture applied
+  # to every face.
+  field SFString{"brick", "a|b"} texture "brick"
+
+  # Separated by a blank line.
+  field SFNode appearance NULL
+]
+{
+  Solid {
+  }
+}
+"#;
+
+    #[test]
+    fn markdown_documentation() {
+        let documentation = documentation(WALL, DocFormat::Markdown).unwrap();
+        assert_eq!(
+            documentation,
+            r#"# Wall
+
+A wall made of bricks.
+
+**Tags:** nonDeterministic  
+**License:** [Apache License 2.0](https://www.apache.org/licenses/LICENSE-2.0)
+
+## Fields
+
+| Kind | Type | Name | Default | Restrictions | Description |
+| --- | --- | --- | --- | --- | --- |
+| field | SFVec3f | `size` | `1 1 1` |  | Size of the wall, in meters. |
+| field | SFString | `texture` | `"brick"` | `"brick"`, `"a\|b"` | Texture applied to every face. |
+| field | SFNode | `appearance` | `NULL` |  | Separated by a blank line. |
+
+"#
+        );
+    }
+
+    #[test]
+    fn html_documentation() {
+        let documentation = documentation(WALL, DocFormat::Html).unwrap();
+        assert!(documentation.starts_with("<h1>Wall</h1>\n<p>A wall made of bricks.</p>\n"));
+        assert!(documentation.contains(
+            "  <dt>License</dt><dd><a href=\"https://www.apache.org/licenses/LICENSE-2.0\">Apache License 2.0</a></dd>\n"
+        ));
+        assert!(documentation
+            .contains("<td><code>&quot;brick&quot;</code>, <code>&quot;a|b&quot;</code></td>"));
+    }
+
+    #[test]
+    fn protos_without_fields() {
+        let code = "#VRML_SIM R2023b utf8\nPROTO Empty [\n]\n{\n  Group {\n  }\n}\n";
+        assert_eq!(
+            documentation(code, DocFormat::Markdown).unwrap(),
+            "# Empty\n\n## Fields\n\nThis PROTO has no fields.\n\n"
+        );
+        assert_eq!(
+            documentation("#VRML_SIM R2023b utf8\nGroup {\n}\n", DocFormat::Markdown)
+                .unwrap_err()
+                .to_string(),
+            "No PROTO to document."
+        );
+    }
+
+    #[test]
+    fn code_spans_and_escapes() {
+        assert_eq!(code_span("a`b"), "`` a`b ``");
+        assert_eq!(code_span("1 2"), "`1 2`");
+        assert_eq!(
+            escape("<a href=\"x\">&</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+}
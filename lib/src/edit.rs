@@ -0,0 +1,119 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+/// Replacement of the bytes `start..end` of a source. Insertions have `start == end`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub start: usize,
+    pub end: usize,
+    pub replacement: String,
+}
+
+impl Edit {
+    pub fn insert(at: usize, text: impl Into<String>) -> Edit {
+        Edit {
+            start: at,
+            end: at,
+            replacement: text.into(),
+        }
+    }
+
+    pub fn delete(start: usize, end: usize) -> Edit {
+        Edit {
+            start,
+            end,
+            replacement: String::new(),
+        }
+    }
+
+    /// Deletes `start..end` along with the rest of the line, if it is blank. The line break is
+    /// only deleted with the whole line.
+    pub fn delete_line(code: &str, start: usize, end: usize) -> Edit {
+        let line_start = code[..start].rfind('\n').map_or(0, |i| i + 1);
+        let whole_line = code[line_start..start].trim().is_empty();
+        let start = if whole_line { line_start } else { start };
+        let rest = &code[end..];
+        let line_end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+        let end = match rest[..line_end].trim().is_empty() {
+            true if whole_line => end + line_end,
+            true => end + rest[..line_end].trim_end_matches('\n').len(),
+            false => end,
+        };
+        Edit::delete(start, end)
+    }
+}
+
+/// Applies `edits` to `code`. Edits are applied by position, and an edit overlapping one
+/// before it is dropped. Insertions at the same position keep their relative order.
+pub fn apply_edits(code: &str, edits: &[Edit]) -> String {
+    let mut edits: Vec<&Edit> = edits.iter().collect();
+    edits.sort_by_key(|edit| edit.start);
+
+    let mut result = String::with_capacity(code.len());
+    let mut last_end = 0;
+    for edit in edits {
+        if edit.start < last_end {
+            continue;
+        }
+        result += &code[last_end..edit.start];
+        result += edit.replacement.as_str();
+        last_end = edit.end;
+    }
+    result += &code[last_end..];
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_apply_by_position() {
+        let code = "Solid {\n  name \"a\"\n}\n";
+        let edits = [
+            Edit::insert(code.len(), "# end\n"),
+            Edit {
+                start: 16,
+                end: 17,
+                replacement: "b".to_string(),
+            },
+            Edit::insert(0, "DEF A "),
+        ];
+        assert_eq!(
+            apply_edits(code, &edits),
+            "DEF A Solid {\n  name \"b\"\n}\n# end\n"
+        );
+    }
+
+    #[test]
+    fn overlapping_edits_are_dropped() {
+        let edits = [Edit::delete(0, 4), Edit::delete(2, 6), Edit::insert(6, "!")];
+        assert_eq!(apply_edits("abcdefgh", &edits), "ef!gh");
+    }
+
+    #[test]
+    fn insertions_at_the_same_position_keep_their_order() {
+        let edits = [Edit::insert(1, "x"), Edit::insert(1, "y")];
+        assert_eq!(apply_edits("ab", &edits), "axyb");
+    }
+
+    #[test]
+    fn delete_line_removes_blank_lines_only() {
+        let code = "a\n  b\nc d  \ne\n";
+        let b = code.find('b').unwrap();
+        assert_eq!(Edit::delete_line(code, b, b + 1), Edit::delete(2, 6));
+        let d = code.find('d').unwrap();
+        assert_eq!(
+            apply_edits(code, &[Edit::delete_line(code, d, d + 1)]),
+            "a\n  b\nc \ne\n"
+        );
+        let c = code.find('c').unwrap();
+        assert_eq!(
+            apply_edits(code, &[Edit::delete_line(code, c, c + 1)]),
+            "a\n  b\n d  \ne\n"
+        );
+    }
+}
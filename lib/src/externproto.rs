@@ -0,0 +1,633 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use tree_sitter::{Node, Range};
+
+use super::config::ExternProtoConfig;
+use super::edit::Edit;
+use super::interface::protos;
+use super::lint::{Context, Report, Rule, Severity};
+use super::schema::BUILTIN_NODES;
+use super::syntax::NodeForm;
+
+/// URL prefix of the PROTOs distributed with Webots, followed by the release and the path inside
+/// the Webots repository.
+pub const WEBOTS_GITHUB: &str = "https://raw.githubusercontent.com/cyberbotics/webots/";
+
+/// An `EXTERNPROTO "url"` declaration.
+#[derive(Debug, Clone, Copy)]
+pub struct ExternProto<'a> {
+    pub node: Node<'a>,
+    pub url_node: Node<'a>,
+    pub url: &'a str,
+    pub importable: bool,
+}
+
+impl<'a> ExternProto<'a> {
+    /// The name of the declared PROTO: the file name of the URL without extension.
+    pub fn name(&self) -> &'a str {
+        let file = self.url.rsplit('/').next().unwrap_or(self.url);
+        file.strip_suffix(".proto").unwrap_or(file)
+    }
+}
+
+pub fn externprotos<'a>(root: Node<'a>, code: &'a str) -> Vec<ExternProto<'a>> {
+    let mut cursor = root.walk();
+    let externs: Vec<Node> = root
+        .children(&mut cursor)
+        .filter(|n| n.kind() == "extern")
+        .collect();
+
+    externs
+        .into_iter()
+        .filter_map(|node| {
+            let mut cursor = node.walk();
+            let children: Vec<Node> = node.children(&mut cursor).collect();
+            let text = |n: &Node<'a>| n.utf8_text(code.as_bytes()).unwrap_or_default();
+            let url_node = *children.iter().find(|c| text(c).starts_with('"'))?;
+            Some(ExternProto {
+                node,
+                url_node,
+                url: text(&url_node).trim_matches('"'),
+                importable: children.iter().any(|c| text(c) == "IMPORTABLE"),
+            })
+        })
+        .collect()
+}
+
+/// Maps EXTERNPROTO URLs to local files, without network access.
+pub struct Resolver<'c> {
+    config: &'c ExternProtoConfig,
+}
+
+impl<'c> Resolver<'c> {
+    pub fn new(config: &'c ExternProtoConfig) -> Resolver<'c> {
+        Resolver { config }
+    }
+
+    /// The local file `url` refers to. Relative URLs are resolved against the directory of
+    /// `declaring_file`, or the current directory if there is none. Fails with the reason the
+    /// URL can't be resolved offline.
+    pub fn resolve(&self, url: &str, declaring_file: Option<&Path>) -> Result<PathBuf, String> {
+        let path = if let Some(path) = url.strip_prefix("webots://") {
+            self.webots_home()?.join(path)
+        } else if url.starts_with("http://") || url.starts_with("https://") {
+            self.map_url(url)?
+        } else {
+            let base = declaring_file
+                .and_then(Path::parent)
+                .unwrap_or_else(|| Path::new("."));
+            base.join(url)
+        };
+
+        if path.is_file() {
+            Ok(path)
+        } else {
+            Err(format!("{} does not exist", path.display()))
+        }
+    }
+
+    fn webots_home(&self) -> Result<&Path, String> {
+        self.config
+            .webots_home
+            .as_deref()
+            .ok_or_else(|| "WEBOTS_HOME is not configured".to_string())
+    }
+
+    fn map_url(&self, url: &str) -> Result<PathBuf, String> {
+        let mapping = self
+            .config
+            .mappings
+            .iter()
+            .filter(|(prefix, _)| url.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len());
+        if let Some((prefix, dir)) = mapping {
+            return Ok(dir.join(&url[prefix.len()..]));
+        }
+
+        if let Some(rest) = url.strip_prefix(WEBOTS_GITHUB) {
+            let path = rest
+                .split_once('/')
+                .map(|(_, path)| path)
+                .unwrap_or_default();
+            return Ok(self.webots_home()?.join(path));
+        }
+
+        Err(format!("no local mapping for {url}"))
+    }
+}
+
+/// EXTERNPROTO declarations that do not resolve to a local file.
+pub struct UnresolvedExternProto;
+
+impl Rule for UnresolvedExternProto {
+    fn id(&self) -> &'static str {
+        "unresolved-externproto"
+    }
+
+    fn description(&self) -> &'static str {
+        "EXTERNPROTO that cannot be resolved to a local file"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, context: &Context, report: &mut Report) {
+        let resolver = Resolver::new(&context.config.externproto);
+        for externproto in externprotos(context.root, context.code) {
+            if let Err(reason) = resolver.resolve(externproto.url, context.path) {
+                let message = format!("cannot resolve {}: {reason}", externproto.url);
+                report.add(externproto.url_node.range(), message);
+            }
+        }
+    }
+}
+
+/// Node types instantiated in the tree, that is, the types of its `DEF` and plain nodes.
+pub fn instantiated_types(root: Node) -> Vec<Node> {
+    let mut types = Vec::new();
+    collect_types(root, &mut types);
+    types
+}
+
+fn collect_types<'a>(node: Node<'a>, types: &mut Vec<Node<'a>>) {
+    if node.kind() == "node" {
+        if let Some(node_type) = NodeForm::new(node).and_then(|form| form.node_type()) {
+            types.push(node_type);
+        }
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_types(child, types);
+    }
+}
+
+/// The names of the PROTOs defined in the file, which may instantiate themselves.
+fn proto_names<'a>(context: &Context<'a>) -> Vec<&'a str> {
+    protos(context.root)
+        .into_iter()
+        .filter_map(|proto| proto.child_by_field_name("proto"))
+        .map(|name| context.text(name))
+        .collect()
+}
+
+/// Looks for `name.proto` next to the declaring file, in a sibling `protos` directory, in the
+/// configured search paths and in the Webots projects, and returns the URL to declare it with.
+fn locate(name: &str, context: &Context) -> Option<String> {
+    let file_name = format!("{name}.proto");
+    let base = context
+        .path
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    let config = &context.config.externproto;
+    let local = [base.clone(), base.join("../protos")]
+        .into_iter()
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.is_file())
+        .or_else(|| {
+            config
+                .search_paths
+                .iter()
+                .find_map(|dir| find_file(dir, file_name.as_str()))
+        });
+    if let Some(path) = local {
+        return relative_url(path.as_path(), base.as_path());
+    }
+
+    let projects = config.webots_home.as_ref()?.join("projects");
+    let path = find_file(projects.as_path(), file_name.as_str())?;
+    let path = path.strip_prefix(config.webots_home.as_ref()?).ok()?;
+    Some(format!("webots://{}", url_path(path)))
+}
+
+/// Searches `dir` and its subdirectories, in name order, for a file called `file_name`.
+fn find_file(dir: &Path, file_name: &str) -> Option<PathBuf> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+    if let Some(path) = entries
+        .iter()
+        .find(|path| path.is_file() && path.file_name().is_some_and(|n| n == file_name))
+    {
+        return Some(path.clone());
+    }
+    entries
+        .iter()
+        .filter(|path| path.is_dir())
+        .find_map(|path| find_file(path, file_name))
+}
+
+/// The URL of `path` relative to the directory `base`.
+fn relative_url(path: &Path, base: &Path) -> Option<String> {
+    let path = path.canonicalize().ok()?;
+    let base = base.canonicalize().ok()?;
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    for _ in base.components().skip(common) {
+        relative.push("..");
+    }
+    relative.extend(path.components().skip(common));
+    Some(url_path(relative.as_path()))
+}
+
+fn url_path(path: &Path) -> String {
+    let parts: Vec<String> = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    parts.join("/")
+}
+
+/// A node type use, with the other places the same type is used.
+type Uses<'a> = (Node<'a>, &'a str, Vec<(Range, String)>);
+
+/// Node types that are neither built-in nor declared by an EXTERNPROTO.
+pub struct MissingExternProto;
+
+impl Rule for MissingExternProto {
+    fn id(&self) -> &'static str {
+        "missing-externproto"
+    }
+
+    fn description(&self) -> &'static str {
+        "node type that is neither built-in nor declared by an EXTERNPROTO"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, context: &Context, report: &mut Report) {
+        let declarations = externprotos(context.root, context.code);
+        let mut known: HashSet<&str> = declarations.iter().map(|e| e.name()).collect();
+        known.extend(BUILTIN_NODES);
+        known.extend(proto_names(context));
+
+        // Each missing type, at its first use, with the other uses.
+        let mut missing: Vec<Uses> = Vec::new();
+        for node_type in instantiated_types(context.root) {
+            let name = context.text(node_type);
+            if known.contains(name) {
+                continue;
+            }
+            match missing.iter_mut().find(|(_, n, _)| *n == name) {
+                Some((_, _, related)) => related.push((node_type.range(), "also used here".into())),
+                None => missing.push((node_type, name, Vec::new())),
+            }
+        }
+
+        // The declarations are inserted as one block, with the first diagnostic that has a fix,
+        // so that they end up together with a blank line after them.
+        let urls: Vec<Option<String>> = missing
+            .iter()
+            .map(|(_, name, _)| locate(name, context))
+            .collect();
+        let lines: Vec<String> = urls
+            .iter()
+            .flatten()
+            .map(|url| format!("EXTERNPROTO \"{url}\""))
+            .collect();
+        let mut fix = if lines.is_empty() {
+            None
+        } else if let Some(last) = declarations.last() {
+            Some(Edit::insert(
+                last.node.end_byte(),
+                format!("\n{}", lines.join("\n")),
+            ))
+        } else {
+            let mut cursor = context.root.walk();
+            let at = context
+                .root
+                .children(&mut cursor)
+                .find(|n| n.kind() != "comment")
+                .map_or(context.code.len(), |n| n.start_byte());
+            Some(Edit::insert(at, format!("{}\n\n", lines.join("\n"))))
+        };
+
+        for ((node_type, name, related), url) in missing.into_iter().zip(urls) {
+            let message = match &url {
+                Some(url) => format!("{name} is not declared, add EXTERNPROTO \"{url}\""),
+                None => format!("{name} is neither a built-in node nor declared by an EXTERNPROTO"),
+            };
+            let diagnostic = report.add(node_type.range(), message);
+            diagnostic.related = related;
+            if url.is_some() {
+                diagnostic.fix.extend(fix.take());
+            }
+        }
+    }
+}
+
+/// EXTERNPROTOs that are never instantiated.
+pub struct UnusedExternProto;
+
+impl Rule for UnusedExternProto {
+    fn id(&self) -> &'static str {
+        "unused-externproto"
+    }
+
+    fn description(&self) -> &'static str {
+        "EXTERNPROTO that is never instantiated"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, context: &Context, report: &mut Report) {
+        let used: HashSet<&str> = instantiated_types(context.root)
+            .into_iter()
+            .map(|node_type| context.text(node_type))
+            .collect();
+        let templates = template_code(context.root, context.code);
+
+        for externproto in externprotos(context.root, context.code) {
+            let name = externproto.name();
+            // IMPORTABLE PROTOs are meant for the scene tree, and templates may generate nodes.
+            if externproto.importable
+                || used.contains(name)
+                || templates.iter().any(|code| code.contains(name))
+            {
+                continue;
+            }
+            let message = format!("{name} is declared but never instantiated");
+            let node = externproto.node;
+            report
+                .add(node.range(), message)
+                .fix
+                .push(Edit::delete_line(
+                    context.code,
+                    node.start_byte(),
+                    node.end_byte(),
+                ));
+        }
+    }
+}
+
+fn template_code<'a>(node: Node, code: &'a str) -> Vec<&'a str> {
+    if matches!(node.kind(), "javascript_block" | "javascript_expression") {
+        return vec![node.utf8_text(code.as_bytes()).unwrap_or_default()];
+    }
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node.children(&mut cursor).collect();
+    children
+        .into_iter()
+        .flat_map(|child| template_code(child, code))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lint::tests::{check_at, fix, fix_at, messages};
+    use crate::syntax::parse;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn declarations_and_names() {
+        let code = r#"#VRML_SIM R2023b utf8
+EXTERNPROTO "webots://projects/objects/floors/protos/RectangleArena.proto"
+IMPORTABLE EXTERNPROTO "../protos/Wall.proto"
+"#;
+        let tree = parse(code).unwrap();
+        let declarations = externprotos(tree.root_node(), code);
+        let found: Vec<(&str, &str, bool)> = declarations
+            .iter()
+            .map(|e| (e.url, e.name(), e.importable))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "webots://projects/objects/floors/protos/RectangleArena.proto",
+                    "RectangleArena",
+                    false
+                ),
+                ("../protos/Wall.proto", "Wall", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn urls_resolve_to_local_files() {
+        let dir = TempDir::new("resolve");
+        for file in [
+            "home/projects/Arena.proto",
+            "shared/Brick.proto",
+            "world/Wall.proto",
+        ] {
+            dir.write(file, "");
+        }
+        let mut config = ExternProtoConfig {
+            webots_home: Some(dir.join("home")),
+            ..Default::default()
+        };
+        config.mappings.insert(
+            "https://example.com/protos/".to_string(),
+            dir.join("shared"),
+        );
+        let resolver = Resolver::new(&config);
+        let world = dir.join("world/world.wbt");
+
+        let resolve = |url: &str| resolver.resolve(url, Some(world.as_path()));
+        assert_eq!(resolve("Wall.proto"), Ok(dir.join("world/Wall.proto")));
+        assert_eq!(
+            resolve("webots://projects/Arena.proto"),
+            Ok(dir.join("home/projects/Arena.proto"))
+        );
+        assert_eq!(
+            resolve(&format!("{WEBOTS_GITHUB}R2023b/projects/Arena.proto")),
+            Ok(dir.join("home/projects/Arena.proto"))
+        );
+        assert_eq!(
+            resolve("https://example.com/protos/Brick.proto"),
+            Ok(dir.join("shared/Brick.proto"))
+        );
+        assert_eq!(
+            resolve("https://example.org/Brick.proto"),
+            Err("no local mapping for https://example.org/Brick.proto".to_string())
+        );
+        assert!(resolve("Door.proto").is_err_and(|reason| reason.ends_with("does not exist")));
+
+        let unconfigured = ExternProtoConfig::default();
+        assert_eq!(
+            Resolver::new(&unconfigured).resolve("webots://projects/Arena.proto", None),
+            Err("WEBOTS_HOME is not configured".to_string())
+        );
+    }
+
+    #[test]
+    fn unresolved_externprotos_are_reported() {
+        let code = r#"#VRML_SIM R2023b utf8
+EXTERNPROTO "webots://projects/objects/floors/protos/RectangleArena.proto"
+
+RectangleArena {
+}
+"#;
+        assert_eq!(
+            messages("unresolved-externproto", code),
+            [(
+                2,
+                "cannot resolve webots://projects/objects/floors/protos/RectangleArena.proto: \
+                 WEBOTS_HOME is not configured"
+                    .to_string()
+            )]
+        );
+        assert_eq!(fix("unresolved-externproto", code), code);
+    }
+
+    #[test]
+    fn missing_externprotos_are_located_and_declared() {
+        let dir = TempDir::new("missing");
+        for file in ["worlds/Wall.proto", "protos/Door.proto"] {
+            dir.write(file, "");
+        }
+        let world = dir.join("worlds/room.wbt");
+        let code = r#"#VRML_SIM R2023b utf8
+
+Wall {
+}
+DEF DOOR Door {
+}
+Wall {
+}
+Lamp {
+}
+Solid {
+}
+"#;
+        let diagnostics = check_at("missing-externproto", code, Some(world.as_path()));
+        let found: Vec<(usize, &str, usize)> = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d.range.start_point.row + 1,
+                    d.message.as_str(),
+                    d.related.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (3, "Wall is not declared, add EXTERNPROTO \"Wall.proto\"", 1),
+                (
+                    5,
+                    "Door is not declared, add EXTERNPROTO \"../protos/Door.proto\"",
+                    0
+                ),
+                (
+                    9,
+                    "Lamp is neither a built-in node nor declared by an EXTERNPROTO",
+                    0
+                ),
+            ]
+        );
+        assert_eq!(
+            fix_at("missing-externproto", code, Some(world.as_path())),
+            r#"#VRML_SIM R2023b utf8
+
+EXTERNPROTO "Wall.proto"
+EXTERNPROTO "../protos/Door.proto"
+
+Wall {
+}
+DEF DOOR Door {
+}
+Wall {
+}
+Lamp {
+}
+Solid {
+}
+"#
+        );
+
+        let declared = code.replacen("\n\n", "\nEXTERNPROTO \"Lamp.proto\"\n\n", 1);
+        assert_eq!(
+            fix_at("missing-externproto", &declared, Some(world.as_path())),
+            r#"#VRML_SIM R2023b utf8
+EXTERNPROTO "Lamp.proto"
+EXTERNPROTO "Wall.proto"
+EXTERNPROTO "../protos/Door.proto"
+
+Wall {
+}
+DEF DOOR Door {
+}
+Wall {
+}
+Lamp {
+}
+Solid {
+}
+"#
+        );
+    }
+
+    #[test]
+    fn protos_defined_in_the_file_are_known() {
+        let code = r#"#VRML_SIM R2023b utf8
+PROTO Tree [
+]
+{
+  Transform {
+    children [
+      Tree {
+      }
+    ]
+  }
+}
+"#;
+        assert_eq!(messages("missing-externproto", code), []);
+    }
+
+    #[test]
+    fn unused_externprotos_are_removed() {
+        let code = r#"#VRML_SIM R2023b utf8
+EXTERNPROTO "Wall.proto"
+IMPORTABLE EXTERNPROTO "Door.proto"
+EXTERNPROTO "https://example.com/protos/Lamp.proto"
+EXTERNPROTO "Table.proto"
+
+PROTO Room [
+]
+{
+  Group {
+    children [
+      Wall {
+      }
+      %< const furniture = 'Table'; >%
+    ]
+  }
+}
+"#;
+        assert_eq!(
+            messages("unused-externproto", code),
+            [(4, "Lamp is declared but never instantiated".to_string())]
+        );
+        assert_eq!(
+            fix("unused-externproto", code),
+            code.replace(
+                "EXTERNPROTO \"https://example.com/protos/Lamp.proto\"\n",
+                ""
+            )
+        );
+    }
+}
@@ -0,0 +1,456 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use tree_sitter::Node;
+
+use super::defaults::{same_value, tokens};
+use super::header::Header;
+use super::schema::field_schema;
+use super::syntax::{parse_valid, text, NodeForm, Property};
+
+/// A condition on a field of the nodes searched for, written `width>640`, `name="wall"`, `physics`
+/// for fields that are set or `!boundingObject` for fields that are not.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldFilter {
+    pub name: String,
+    pub test: FieldTest,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldTest {
+    Present,
+    Absent,
+    Compare(Comparison, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl FromStr for FieldFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<FieldFilter> {
+        const OPERATORS: [(&str, Comparison); 6] = [
+            (">=", Comparison::Ge),
+            ("<=", Comparison::Le),
+            ("!=", Comparison::Ne),
+            ("=", Comparison::Eq),
+            (">", Comparison::Gt),
+            ("<", Comparison::Lt),
+        ];
+
+        let (name, test) = match s.strip_prefix('!') {
+            Some(name) => (name, FieldTest::Absent),
+            None => match s.find(['<', '>', '=', '!']) {
+                Some(i) => {
+                    let (name, rest) = s.split_at(i);
+                    let (comparison, value) = OPERATORS
+                        .iter()
+                        .find_map(|(operator, comparison)| {
+                            rest.strip_prefix(operator)
+                                .map(|value| (*comparison, value.trim()))
+                        })
+                        .ok_or_else(|| anyhow!("Unknown comparison in \"{s}\""))?;
+                    (name, FieldTest::Compare(comparison, value.to_string()))
+                }
+                None => (s, FieldTest::Present),
+            },
+        };
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(anyhow!(
+                "Invalid field condition \"{s}\", expected FIELD, !FIELD or FIELD<OPERATOR>VALUE"
+            ));
+        }
+        Ok(FieldFilter {
+            name: name.to_string(),
+            test,
+        })
+    }
+}
+
+/// What the nodes searched for must match: one of the node types, if any, a DEF name matching the
+/// glob pattern, if any, and every field filter.
+#[derive(Debug, Clone, Default)]
+pub struct FindCriteria {
+    pub node_types: Vec<String>,
+    pub def_name: Option<String>,
+    pub fields: Vec<FieldFilter>,
+}
+
+/// A node matching the criteria of a search.
+#[derive(Debug, Clone)]
+pub struct FoundNode {
+    /// The 1-based position of the node.
+    pub line: usize,
+    pub column: usize,
+    pub node_type: String,
+    pub def: Option<String>,
+    /// The fields named by the filters that are set, or have a default value.
+    pub fields: Vec<FoundField>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FoundField {
+    pub name: String,
+    pub value: String,
+    /// Whether the field is not set and `value` is the default of the built-in node.
+    pub default: bool,
+}
+
+/// The nodes of `code` matching `criteria`, in the order they appear. `USE`s are left out, as they
+/// are the node they refer to. Fields not set in built-in nodes are compared with their default
+/// value, as in Webots; fields bound with `IS` only count as set.
+pub fn find_nodes(code: &str, criteria: &FindCriteria) -> Result<Vec<FoundNode>> {
+    let tree = parse_valid(code)?;
+    let release = Header::from_source(code).release();
+    let mut found = Vec::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        let mut cursor = node.walk();
+        let children: Vec<Node> = node.children(&mut cursor).collect();
+        stack.extend(children.iter().rev());
+        if node.kind() != "node" {
+            continue;
+        }
+        let (def, node_type) = match NodeForm::new(node) {
+            Some(NodeForm::Def { name, node_type }) => {
+                (Some(text(name, code)), text(node_type, code))
+            }
+            Some(NodeForm::Plain { node_type }) => (None, text(node_type, code)),
+            _ => continue,
+        };
+        if !criteria.node_types.is_empty() && !criteria.node_types.iter().any(|t| t == node_type) {
+            continue;
+        }
+        let def_matches = match &criteria.def_name {
+            Some(pattern) => def.is_some_and(|def| glob_match(pattern, def)),
+            None => true,
+        };
+        if !def_matches {
+            continue;
+        }
+
+        let properties: Vec<Property> = children
+            .iter()
+            .filter(|child| child.kind() == "property")
+            .filter_map(|child| Property::new(*child))
+            .collect();
+        let mut fields = Vec::new();
+        let mut matches = true;
+        for filter in &criteria.fields {
+            let property = properties
+                .iter()
+                .find(|property| text(property.name, code) == filter.name);
+            let value = match property {
+                Some(property) if property.is_target.is_some() => None,
+                Some(property) => match (property.values.first(), property.values.last()) {
+                    (Some(first), Some(last)) => {
+                        Some((&code[first.start_byte()..last.end_byte()], false))
+                    }
+                    _ => None,
+                },
+                None => field_schema(node_type, &filter.name, release)
+                    .and_then(|schema| schema.default)
+                    .map(|default| (default, true)),
+            };
+            matches &= match &filter.test {
+                FieldTest::Present => property.is_some(),
+                FieldTest::Absent => property.is_none(),
+                FieldTest::Compare(comparison, expected) => {
+                    value.is_some_and(|(value, _)| compare(value, *comparison, expected))
+                }
+            };
+            if !matches {
+                break;
+            }
+            if let Some((value, default)) = value {
+                fields.push(FoundField {
+                    name: filter.name.clone(),
+                    value: value.to_string(),
+                    default,
+                });
+            }
+        }
+        if !matches {
+            continue;
+        }
+
+        let position = node.start_position();
+        found.push(FoundNode {
+            line: position.row + 1,
+            column: position.column + 1,
+            node_type: node_type.to_string(),
+            def: def.map(str::to_string),
+            fields,
+        });
+    }
+    Ok(found)
+}
+
+/// Compares a field value with the value of a filter. Equality ignores the way values are written,
+/// and strings may be given without quotes. Orderings only apply to single numbers.
+fn compare(value: &str, comparison: Comparison, expected: &str) -> bool {
+    let equal = || same_value(value, expected) || same_value(value, &format!("\"{expected}\""));
+    let ordering = || match (tokens(value).as_slice(), expected.parse::<f64>()) {
+        ([value], Ok(expected)) => value
+            .parse::<f64>()
+            .ok()
+            .and_then(|value| value.partial_cmp(&expected)),
+        _ => None,
+    };
+    match comparison {
+        Comparison::Eq => equal(),
+        Comparison::Ne => !equal(),
+        Comparison::Lt => ordering().is_some_and(|o| o.is_lt()),
+        Comparison::Le => ordering().is_some_and(|o| o.is_le()),
+        Comparison::Gt => ordering().is_some_and(|o| o.is_gt()),
+        Comparison::Ge => ordering().is_some_and(|o| o.is_ge()),
+    }
+}
+
+/// Whether `text` matches the glob `pattern`, where `*` stands for any characters and `?` for one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // The position of the last `*` and of the text it was tried at, to backtrack to.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(s: &str) -> FieldFilter {
+        s.parse().unwrap()
+    }
+
+    fn compared(name: &str, comparison: Comparison, value: &str) -> FieldFilter {
+        FieldFilter {
+            name: name.to_string(),
+            test: FieldTest::Compare(comparison, value.to_string()),
+        }
+    }
+
+    #[test]
+    fn field_filters_parse() {
+        assert_eq!(
+            filter("physics"),
+            FieldFilter {
+                name: "physics".to_string(),
+                test: FieldTest::Present
+            }
+        );
+        assert_eq!(
+            filter("!boundingObject"),
+            FieldFilter {
+                name: "boundingObject".to_string(),
+                test: FieldTest::Absent
+            }
+        );
+        assert_eq!(
+            filter("width>640"),
+            compared("width", Comparison::Gt, "640")
+        );
+        assert_eq!(
+            filter("width >= 640"),
+            compared("width", Comparison::Ge, "640")
+        );
+        assert_eq!(
+            filter("width<640"),
+            compared("width", Comparison::Lt, "640")
+        );
+        assert_eq!(
+            filter("width<=640"),
+            compared("width", Comparison::Le, "640")
+        );
+        assert_eq!(
+            filter("name!=wall"),
+            compared("name", Comparison::Ne, "wall")
+        );
+        assert_eq!(
+            filter("name=\"a = b\""),
+            compared("name", Comparison::Eq, "\"a = b\"")
+        );
+    }
+
+    #[test]
+    fn invalid_field_filters_are_refused() {
+        let error = |s: &str| s.parse::<FieldFilter>().unwrap_err().to_string();
+        assert_eq!(error("width!640"), "Unknown comparison in \"width!640\"");
+        for s in ["", "=640", "!", "my field", "!width>640"] {
+            assert_eq!(
+                error(s),
+                format!(
+                    "Invalid field condition \"{s}\", expected FIELD, !FIELD or FIELD<OPERATOR>VALUE"
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("LEFT_*", "LEFT_WHEEL"));
+        assert!(glob_match("LEFT_*", "LEFT_"));
+        assert!(!glob_match("LEFT_*", "RIGHT_WHEEL"));
+        assert!(glob_match("*_WHEEL", "LEFT_WHEEL"));
+        assert!(glob_match("*ab", "aab"));
+        assert!(glob_match("a*b*c", "aXbYbc"));
+        assert!(!glob_match("a*b*c", "aXbYb"));
+        assert!(glob_match("WHEEL?", "WHEEL1"));
+        assert!(!glob_match("WHEEL?", "WHEEL"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("", "A"));
+        assert!(glob_match("ÉTÉ_?", "ÉTÉ_É"));
+    }
+
+    #[test]
+    fn values_compare() {
+        assert!(compare("0.50", Comparison::Eq, "0.5"));
+        assert!(compare("\"wall\"", Comparison::Eq, "wall"));
+        assert!(compare("\"wall\"", Comparison::Eq, "\"wall\""));
+        assert!(compare("0 0 1", Comparison::Eq, "0 0 1.0"));
+        assert!(compare("\"wall\"", Comparison::Ne, "door"));
+        assert!(compare("1280", Comparison::Gt, "640"));
+        assert!(compare("640", Comparison::Ge, "640"));
+        assert!(!compare("640", Comparison::Lt, "640"));
+        assert!(compare("-1", Comparison::Le, "0"));
+        assert!(!compare("0 0 1", Comparison::Gt, "0"));
+        assert!(!compare("\"b\"", Comparison::Gt, "a"));
+    }
+
+    const WORLD: &str = r#"#VRML_SIM R2023b utf8
+Robot {
+  children [
+    Camera {
+      width 1280
+    }
+    Camera {
+      name "small"
+    }
+    DEF LEFT_WHEEL Solid {
+      physics Physics {
+      }
+    }
+    DEF RIGHT_WHEEL Solid {
+      boundingObject USE BODY
+    }
+    USE LEFT_WHEEL
+  ]
+}
+"#;
+
+    type Found = (
+        usize,
+        usize,
+        String,
+        Option<String>,
+        Vec<(String, String, bool)>,
+    );
+
+    fn find(node_types: &[&str], def_name: Option<&str>, fields: &[&str]) -> Vec<Found> {
+        let criteria = FindCriteria {
+            node_types: node_types.iter().map(|t| t.to_string()).collect(),
+            def_name: def_name.map(str::to_string),
+            fields: fields.iter().map(|f| filter(f)).collect(),
+        };
+        find_nodes(WORLD, &criteria)
+            .unwrap()
+            .into_iter()
+            .map(|node| {
+                let fields = node
+                    .fields
+                    .into_iter()
+                    .map(|field| (field.name, field.value, field.default))
+                    .collect();
+                (node.line, node.column, node.node_type, node.def, fields)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nodes_are_found_by_field_values_and_defaults() {
+        assert_eq!(
+            find(&["Camera"], None, &["width>640"]),
+            [(
+                4,
+                5,
+                "Camera".to_string(),
+                None,
+                vec![("width".to_string(), "1280".to_string(), false)]
+            )]
+        );
+        assert_eq!(
+            find(&["Camera"], None, &["width<=640", "name=small"]),
+            [(
+                7,
+                5,
+                "Camera".to_string(),
+                None,
+                vec![
+                    ("width".to_string(), "64".to_string(), true),
+                    ("name".to_string(), "\"small\"".to_string(), false)
+                ]
+            )]
+        );
+        assert_eq!(find(&["Camera"], None, &["name!=camera"]).len(), 1);
+    }
+
+    #[test]
+    fn nodes_are_found_by_type_def_name_and_presence() {
+        let lines = |found: Vec<Found>| found.iter().map(|f| f.0).collect::<Vec<_>>();
+        assert_eq!(lines(find(&[], None, &[])), [2, 4, 7, 10, 11, 14]);
+        assert_eq!(lines(find(&["Camera", "Robot"], None, &[])), [2, 4, 7]);
+        assert_eq!(lines(find(&[], Some("*_WHEEL"), &[])), [10, 14]);
+        assert_eq!(
+            lines(find(&["Solid"], None, &["physics", "!boundingObject"])),
+            [10]
+        );
+        assert!(find(&["Solid"], Some("RIGHT_*"), &["physics"]).is_empty());
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        let criteria = FindCriteria {
+            node_types: vec!["Solid".to_string()],
+            def_name: None,
+            fields: Vec::new(),
+        };
+        assert_eq!(
+            find_nodes("#VRML_SIM R2023b utf8\nSolid {\n  name ]\n}\n", &criteria)
+                .unwrap_err()
+                .to_string(),
+            "Parsed file contain errors (at line 3)."
+        );
+    }
+}
@@ -0,0 +1,689 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use anyhow::{anyhow, Result};
+use serde_yaml::{Mapping, Value as YamlValue};
+
+use super::ast::{Document, ExternProtoInfo, FieldValue, HeaderInfo, NodeEntry, NodeField, Value};
+use super::header::{Release, HEADER_KEYS};
+use super::schema::field_type;
+
+/// Release written in the header of generated worlds that do not name one.
+const DEFAULT_RELEASE: &str = "R2025a";
+
+/// The code generated from a description, still to be formatted.
+#[derive(Debug, Clone)]
+pub struct Generated {
+    pub code: String,
+    /// Whether the code is a PROTO, as opposed to a world.
+    pub proto: bool,
+}
+
+/// Generates a PROTO or world from `input`, either the document printed by `--emit ast-json` or a
+/// scene description, in JSON or YAML. Scene descriptions list the top-level nodes of a world:
+///
+/// ```yaml
+/// version: R2023b
+/// externprotos: ["webots://projects/objects/floors/protos/RectangleArena.proto"]
+/// nodes:
+///   - WorldInfo: { basicTimeStep: 16 }
+///   - RectangleArena: { floorSize: [4, 4] }
+///   - DEF BOX Solid:
+///       translation: [0, 0, 0.1]
+///       children: [{ DEF BOX_SHAPE Shape: { geometry: { Box: { size: [0.2, 0.2, 0.2] } } } }]
+///       boundingObject: USE BOX_SHAPE
+/// ```
+pub fn generate(input: &str) -> Result<Generated> {
+    // YAML is a superset of JSON, and its mappings keep the order of the fields.
+    let value: YamlValue = serde_yaml::from_str(input)?;
+    let document = if value.get("header").is_some() {
+        serde_yaml::from_value::<Document>(value)
+            .map_err(|err| anyhow!("Invalid AST document: {err}"))?
+    } else {
+        scene(value).map_err(|err| anyhow!("Invalid scene description: {err}"))?
+    };
+    Ok(Generated {
+        code: document_code(&document),
+        proto: document.proto.is_some(),
+    })
+}
+
+/// Prints `document` as PROTO or world code, leaving its formatting to the beautifier.
+pub fn document_code(document: &Document) -> String {
+    let mut printer = Printer {
+        code: String::new(),
+        trailing_comment: false,
+    };
+    printer.header(&document.header);
+
+    for externproto in &document.externprotos {
+        if externproto.importable {
+            printer.code += "IMPORTABLE ";
+        }
+        printer.code += "EXTERNPROTO ";
+        printer.code += string(externproto.url.as_str()).as_str();
+        printer.code += "\n";
+    }
+
+    if let Some(proto) = &document.proto {
+        printer.code += format!("\nPROTO {} [\n", proto.name).as_str();
+        for field in &proto.fields {
+            printer.comments(&field.comments);
+            printer.code += format!("{} {}", field.kind, field.field_type).as_str();
+            if !field.restrictions.is_empty() {
+                printer.code += "{";
+                for (i, restriction) in field.restrictions.iter().enumerate() {
+                    if i != 0 {
+                        printer.code += ", ";
+                    }
+                    printer.value(restriction);
+                }
+                printer.code += "}";
+            }
+            printer.code += format!(" {} ", field.name).as_str();
+            printer.field_value(&field.default);
+            printer.trailing_comment(field.trailing_comment.as_deref());
+            printer.separator("\n");
+        }
+        printer.code += "]\n{\n";
+        for value in &proto.body {
+            printer.value(value);
+            printer.trailing_comment(value.trailing_comment());
+            printer.separator("\n");
+        }
+        printer.code += "}\n";
+    }
+
+    for node in &document.nodes {
+        printer.separator("\n");
+        printer.value(node);
+        printer.trailing_comment(node.trailing_comment());
+    }
+    printer.separator("\n");
+    printer.code
+}
+
+struct Printer {
+    code: String,
+    /// Whether the line ends with a comment, which only a line break can follow.
+    trailing_comment: bool,
+}
+
+impl Printer {
+    fn header(&mut self, header: &HeaderInfo) {
+        let mut lines = Vec::new();
+        if let Some(version) = &header.version {
+            let encoding = header.encoding.as_deref().unwrap_or_default();
+            lines.push(format!("#VRML_SIM {version} {encoding}"));
+        }
+        let values = [
+            header.template_language.clone(),
+            header.license.clone(),
+            header.license_url.clone(),
+            header.documentation_url.clone(),
+            (!header.tags.is_empty()).then(|| header.tags.join(", ")),
+            (!header.keywords.is_empty()).then(|| header.keywords.join(", ")),
+        ];
+        for (key, value) in HEADER_KEYS.iter().zip(values) {
+            if let Some(value) = value {
+                lines.push(format!("# {key}: {value}"));
+            }
+        }
+        lines.extend(header.description.iter().map(|line| comment(line)));
+
+        for line in lines {
+            self.code += line.trim_end();
+            self.code += "\n";
+        }
+        self.code += "\n";
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Bool { value } => self.code += if *value { "TRUE" } else { "FALSE" },
+            Value::Number { value, text } if text.is_empty() => {
+                self.code += value.to_string().as_str()
+            }
+            Value::Number { text, .. } => self.code += text.as_str(),
+            Value::String { value } => self.code += string(value).as_str(),
+            Value::Null => self.code += "NULL",
+            Value::Node {
+                def,
+                node_type,
+                fields,
+                multiline,
+                comments,
+                ..
+            } => {
+                self.comments(comments);
+                if let Some(def) = def {
+                    self.code += format!("DEF {def} ").as_str();
+                }
+                self.code += format!("{node_type} {{").as_str();
+                // Comments above fields need them on lines of their own.
+                let commented = fields.iter().any(
+                    |entry| matches!(entry, NodeEntry::Field(field) if !field.comments.is_empty()),
+                );
+                let separator = if *multiline || commented { "\n" } else { " " };
+                for entry in fields {
+                    self.separator(separator);
+                    match entry {
+                        NodeEntry::Field(field) => self.node_field(field),
+                        NodeEntry::Template {
+                            expression, code, ..
+                        } => self.code += template(*expression, code).as_str(),
+                    }
+                }
+                self.separator(separator);
+                self.code += "}";
+            }
+            Value::Use { name, .. } => self.code += format!("USE {name}").as_str(),
+            Value::Template {
+                expression, code, ..
+            } => self.code += template(*expression, code).as_str(),
+            Value::Other { text } => self.code += text.as_str(),
+        }
+    }
+
+    fn node_field(&mut self, field: &NodeField) {
+        self.comments(&field.comments);
+        self.code += field.name.as_str();
+        if let Some(target) = &field.is {
+            self.code += format!(" IS {target}").as_str();
+        } else {
+            self.code += " ";
+            self.field_value(&field.value);
+        }
+        self.trailing_comment(field.trailing_comment.as_deref());
+    }
+
+    /// Prints a value, with commas after the items the source had them after.
+    fn field_value(&mut self, value: &FieldValue) {
+        let commented = value
+            .items
+            .iter()
+            .any(|item| matches!(item, Value::Node { comments, .. } if !comments.is_empty()));
+        let separator = if value.bracketed && (value.multiline || commented) {
+            "\n"
+        } else {
+            " "
+        };
+        if value.bracketed {
+            self.code += "[";
+        }
+        for (i, item) in value.items.iter().enumerate() {
+            if i != 0 || value.bracketed {
+                self.separator(separator);
+            }
+            self.value(item);
+            if value.commas.contains(&i) {
+                self.code += ",";
+            }
+            self.trailing_comment(item.trailing_comment());
+        }
+        if value.bracketed {
+            self.separator(separator);
+            self.code += "]";
+        }
+    }
+
+    /// Prints comments on lines of their own.
+    fn comments(&mut self, comments: &[String]) {
+        for line in comments {
+            self.code += comment(line).as_str();
+            self.code += "\n";
+        }
+    }
+
+    fn trailing_comment(&mut self, trailing_comment: Option<&str>) {
+        if let Some(line) = trailing_comment {
+            self.code += " ";
+            self.code += comment(line).as_str();
+            self.trailing_comment = true;
+        }
+    }
+
+    /// Prints `separator`, or a line break after a trailing comment.
+    fn separator(&mut self, separator: &str) {
+        self.code += if self.trailing_comment {
+            "\n"
+        } else {
+            separator
+        };
+        self.trailing_comment = false;
+    }
+}
+
+/// Comments are written as they were exported, `#` included, but may also be given as plain text.
+fn comment(line: &str) -> String {
+    if line.is_empty() || line.starts_with('#') {
+        line.to_string()
+    } else {
+        format!("# {line}")
+    }
+}
+
+fn template(expression: bool, code: &str) -> String {
+    let opener = if expression { "%<=" } else { "%<" };
+    if code.contains('\n') {
+        format!("{opener}{code}>%")
+    } else {
+        format!("{opener} {} >%", code.trim())
+    }
+}
+
+fn string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Builds the document of a world from a scene description.
+fn scene(value: YamlValue) -> Result<Document> {
+    let mut scene = match value {
+        YamlValue::Mapping(mapping) => mapping,
+        YamlValue::Null => Mapping::new(),
+        _ => return Err(anyhow!("expected a mapping")),
+    };
+    if let Some((key, _)) = scene
+        .iter()
+        .find(|(key, _)| !matches!(key.as_str(), Some("version" | "externprotos" | "nodes")))
+    {
+        return Err(anyhow!("unknown key {}", yaml_text(key)));
+    }
+
+    let version = match scene.remove("version") {
+        Some(YamlValue::String(version)) if Release::parse(version.as_str()).is_some() => version,
+        Some(version) => return Err(anyhow!("invalid version {}", yaml_text(&version))),
+        None => DEFAULT_RELEASE.to_string(),
+    };
+    let release = Release::parse(version.as_str());
+
+    let externprotos = sequence(scene.remove("externprotos"))?
+        .into_iter()
+        .map(|url| match url {
+            YamlValue::String(url) => Ok(ExternProtoInfo {
+                url,
+                importable: false,
+                line: 0,
+            }),
+            url => Err(anyhow!("invalid EXTERNPROTO URL {}", yaml_text(&url))),
+        })
+        .collect::<Result<_>>()?;
+
+    let nodes = sequence(scene.remove("nodes"))?
+        .into_iter()
+        .map(|node| scene_node(node, release))
+        .collect::<Result<_>>()?;
+
+    Ok(Document {
+        header: HeaderInfo {
+            version: Some(version),
+            encoding: Some("utf8".to_string()),
+            ..HeaderInfo::default()
+        },
+        externprotos,
+        proto: None,
+        nodes,
+    })
+}
+
+fn sequence(value: Option<YamlValue>) -> Result<Vec<YamlValue>> {
+    match value {
+        None | Some(YamlValue::Null) => Ok(Vec::new()),
+        Some(YamlValue::Sequence(items)) => Ok(items),
+        Some(value) => Err(anyhow!("expected a list, found {}", yaml_text(&value))),
+    }
+}
+
+/// A node, written as a mapping from its type, or `DEF NAME` and its type, to its fields, or a
+/// `USE NAME` string.
+fn scene_node(value: YamlValue, release: Option<Release>) -> Result<Value> {
+    if let Some(name) = value.as_str().and_then(|text| text.strip_prefix("USE ")) {
+        return Ok(Value::Use {
+            name: name.trim().to_string(),
+            line: 0,
+        });
+    }
+    let node = match value {
+        YamlValue::Mapping(node) if node.len() == 1 => node,
+        value => return Err(anyhow!("expected a node, found {}", yaml_text(&value))),
+    };
+    let Some((YamlValue::String(head), fields)) = node.into_iter().next() else {
+        return Err(anyhow!("expected a node type"));
+    };
+    let words: Vec<&str> = head.split_whitespace().collect();
+    let (def, node_type) = match words[..] {
+        [node_type] => (None, node_type),
+        ["DEF", name, node_type] => (Some(name.to_string()), node_type),
+        _ => return Err(anyhow!("invalid node \"{head}\"")),
+    };
+    let fields = match fields {
+        YamlValue::Null => Mapping::new(),
+        YamlValue::Mapping(fields) => fields,
+        fields => {
+            return Err(anyhow!(
+                "expected the fields of {node_type}, found {}",
+                yaml_text(&fields)
+            ))
+        }
+    };
+
+    let mut entries = Vec::new();
+    for (name, value) in fields {
+        let YamlValue::String(name) = name else {
+            return Err(anyhow!("invalid field name {}", yaml_text(&name)));
+        };
+        let field_type = field_type(node_type, name.as_str(), release);
+        let value = scene_value(value, field_type, release)
+            .map_err(|err| anyhow!("{node_type}.{name}: {err}"))?;
+        entries.push(NodeEntry::Field(NodeField {
+            name,
+            is: None,
+            value,
+            comments: Vec::new(),
+            trailing_comment: None,
+            line: 0,
+        }));
+    }
+
+    Ok(Value::Node {
+        def,
+        node_type: node_type.to_string(),
+        multiline: !entries.is_empty(),
+        fields: entries,
+        comments: Vec::new(),
+        trailing_comment: None,
+        line: 0,
+    })
+}
+
+/// A field value. Lists are written between brackets for multiple value fields, and, when the type
+/// of the field is unknown, unless they only hold numbers, as vectors and colors do.
+fn scene_value(
+    value: YamlValue,
+    field_type: Option<&str>,
+    release: Option<Release>,
+) -> Result<FieldValue> {
+    let values = match value {
+        YamlValue::Sequence(values) => values,
+        value => vec![value],
+    };
+    let mut items = Vec::new();
+    let mut commas = Vec::new();
+    let mut nested = false;
+    for value in values {
+        match value {
+            YamlValue::Sequence(group) => {
+                nested = true;
+                for value in group {
+                    items.push(scene_item(value, release)?);
+                }
+                commas.extend(items.len().checked_sub(1));
+            }
+            value => items.push(scene_item(value, release)?),
+        }
+    }
+    // Nested lists are separated by commas.
+    commas.dedup();
+    commas.retain(|i| i + 1 < items.len());
+
+    let numbers = items
+        .iter()
+        .all(|item| matches!(item, Value::Number { .. }));
+    let bracketed = match field_type {
+        Some(field_type) => field_type.starts_with("MF"),
+        None => nested || !numbers || items.is_empty(),
+    };
+    let multiline = items
+        .iter()
+        .any(|item| matches!(item, Value::Node { .. } | Value::Use { .. }));
+    Ok(FieldValue {
+        bracketed,
+        multiline: bracketed && multiline,
+        items,
+        commas,
+    })
+}
+
+fn scene_item(value: YamlValue, release: Option<Release>) -> Result<Value> {
+    Ok(match value {
+        YamlValue::Null => Value::Null,
+        YamlValue::Bool(value) => Value::Bool { value },
+        YamlValue::Number(number) => Value::Number {
+            value: number.as_f64().unwrap_or_default(),
+            text: number.to_string(),
+        },
+        YamlValue::String(text) if text.starts_with("USE ") => scene_node(text.into(), release)?,
+        YamlValue::String(value) => Value::String { value },
+        YamlValue::Mapping(_) => scene_node(value, release)?,
+        value => return Err(anyhow!("unexpected value {}", yaml_text(&value))),
+    })
+}
+
+/// A value, as it would be written in the description.
+fn yaml_text(value: &YamlValue) -> String {
+    serde_yaml::to_string(value)
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{Arguments, Parser};
+    use crate::ast::ast_json;
+    use crate::beautifier::beautify;
+
+    fn format(code: &str) -> String {
+        let mut arguments = Arguments::parse_from(["wbproto-beautifier", "--inplace"]);
+        beautify(code, &mut arguments).unwrap()
+    }
+
+    /// Exports `code`, generates it back and checks that it formats as the original does.
+    fn assert_round_trip(code: &str) {
+        let json = ast_json(code, true).unwrap();
+        let generated = generate(json.as_str()).unwrap();
+        assert_eq!(format(generated.code.as_str()), format(code), "{json}");
+    }
+
+    #[test]
+    fn protos_round_trip() {
+        assert_round_trip(
+            r#"#VRML_SIM R2023b utf8
+# license: Apache License 2.0
+## Wall ##
+# A wall,
+#   made of bricks.
+
+# Second paragraph.
+EXTERNPROTO "Brick.proto"
+
+PROTO Wall [
+  # The size of the wall,
+  # in meters.
+  field SFVec3f size 1 2 0.5  # Width, depth and height.
+  field SFString{"brick", "stone"} material "brick"
+  field MFString urls [ "a.png", "b \"c\".png" ]
+  field MFVec3f points [ 0 0 0, 1 1 1 ]
+  field MFFloat ratios [ 1 2.5 -4e-3 ]
+  field MFNode extensions [ ]
+]
+{
+  # The wall itself.
+  DEF WALL Solid {
+    name IS material  # Bound to the interface.
+    children [
+      # Its shape.
+      DEF SHAPE Shape {
+        geometry IndexedFaceSet {
+          coord Coordinate {
+            point [ 0 0 0, 1 0 0, 1 1 0 ]
+          }
+          coordIndex [ 0 1 2 -1 ]
+        }
+      }
+      Brick { }  # Bricks are added by the template.
+    ]
+    boundingObject USE SHAPE
+  }
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn worlds_round_trip() {
+        assert_round_trip(
+            r#"#VRML_SIM R2023b utf8
+
+WorldInfo {
+  basicTimeStep 16  # In milliseconds.
+}
+# The floor,
+# below the origin.
+DEF FLOOR Solid {
+  translation 0 0 -0.1
+  children [ Shape { } ]
+  locked TRUE
+}
+"#,
+        );
+    }
+
+    #[test]
+    fn commas_and_description_are_kept() {
+        let code = r#"#VRML_SIM R2023b utf8
+## Banner ##
+# Text.
+Coordinate {
+  point [ 0 0 0, 1 0 0 ]
+}
+"#;
+        let document = crate::ast::ast(code).unwrap();
+        assert_eq!(document.header.description, ["## Banner ##", "# Text."]);
+        let Value::Node { fields, .. } = &document.nodes[0] else {
+            panic!("{:?}", document.nodes);
+        };
+        let [NodeEntry::Field(point)] = &fields[..] else {
+            panic!("{fields:?}");
+        };
+        assert_eq!(point.value.commas, [2]);
+        assert!(document_code(&document).contains("## Banner ##\n# Text.\n"));
+        assert!(document_code(&document).contains("point [ 0 0 0, 1 0 0 ]"));
+    }
+
+    #[test]
+    fn documents_without_commas_get_none() {
+        let json = r##"{
+  "header": { "version": "R2023b", "encoding": "utf8", "description": ["Plain text.", "", "# A comment."] },
+  "proto": null,
+  "nodes": [{
+    "kind": "node", "def": null, "type": "Coordinate",
+    "fields": [{
+      "kind": "field", "name": "point", "is": null,
+      "value": { "bracketed": true, "items": [
+        { "kind": "number", "value": 0 }, { "kind": "number", "value": 0 },
+        { "kind": "number", "value": 1 }, { "kind": "number", "value": 1 },
+        { "kind": "number", "value": 0 }, { "kind": "number", "value": 0.5 }
+      ] }
+    }]
+  }]
+}"##;
+        let generated = generate(json).unwrap();
+        assert!(!generated.proto);
+        assert_eq!(
+            generated.code,
+            "#VRML_SIM R2023b utf8\n# Plain text.\n\n# A comment.\n\n\
+             \nCoordinate { point [ 0 0 1 1 0 0.5 ] }\n"
+        );
+    }
+
+    #[test]
+    fn comments_are_printed_back() {
+        let json = r##"{
+  "header": { "version": "R2023b", "encoding": "utf8" },
+  "proto": null,
+  "nodes": [{
+    "kind": "node", "def": null, "type": "Solid", "comments": ["A solid."],
+    "fields": [
+      {
+        "kind": "field", "name": "name", "is": null,
+        "value": { "bracketed": false, "items": [{ "kind": "string", "value": "a" }] },
+        "comments": ["# The name."], "trailing_comment": "# Trailing."
+      },
+      {
+        "kind": "field", "name": "locked", "is": null,
+        "value": { "bracketed": false, "items": [{ "kind": "bool", "value": true }] }
+      }
+    ]
+  }]
+}"##;
+        assert_eq!(
+            generate(json).unwrap().code,
+            "#VRML_SIM R2023b utf8\n\n\n# A solid.\nSolid {\n# The name.\nname \"a\" # Trailing.\n\
+             locked TRUE\n}\n"
+        );
+    }
+
+    #[test]
+    fn scenes_generate_worlds() {
+        let yaml = r#"
+version: R2023b
+externprotos: ["Arena.proto"]
+nodes:
+  - WorldInfo: { basicTimeStep: 16 }
+  - Arena:
+  - DEF BOX Solid:
+      translation: [0, 0, 0.1]
+      children: [{ DEF BOX_SHAPE Shape: {} }]
+      boundingObject: USE BOX_SHAPE
+  - Coordinate: { point: [[0, 0, 0], [1, 0, 0], [1, 1, 0]] }
+"#;
+        assert_eq!(
+            generate(yaml).unwrap().code,
+            r#"#VRML_SIM R2023b utf8
+
+EXTERNPROTO "Arena.proto"
+
+WorldInfo {
+basicTimeStep 16
+}
+Arena { }
+DEF BOX Solid {
+translation 0 0 0.1
+children [
+DEF BOX_SHAPE Shape { }
+]
+boundingObject USE BOX_SHAPE
+}
+Coordinate {
+point [ 0 0 0, 1 0 0, 1 1 0 ]
+}
+"#
+        );
+    }
+
+    #[test]
+    fn invalid_scenes_are_reported() {
+        let error = |yaml| generate(yaml).unwrap_err().to_string();
+        assert_eq!(
+            error("worlds: []"),
+            "Invalid scene description: unknown key worlds"
+        );
+        assert_eq!(
+            error("version: 2023"),
+            "Invalid scene description: invalid version 2023"
+        );
+        assert_eq!(
+            error("nodes: [{ Solid: 1 }]"),
+            "Invalid scene description: expected the fields of Solid, found 1"
+        );
+        assert_eq!(
+            error("nodes: [{ DEF Solid: {} }]"),
+            "Invalid scene description: invalid node \"DEF Solid\""
+        );
+    }
+}
@@ -0,0 +1,480 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::fmt;
+
+/// Structured header keys, in the order they are printed.
+pub const HEADER_KEYS: [&str; 6] = [
+    "template language",
+    "license",
+    "license url",
+    "documentation url",
+    "tags",
+    "keywords",
+];
+
+/// Tags Webots understands in the `# tags:` header line.
+pub const KNOWN_TAGS: [&str; 4] = ["deprecated", "hidden", "nonDeterministic", "no3dView"];
+
+/// Languages accepted by `# template language:`.
+pub const TEMPLATE_LANGUAGES: [&str; 2] = ["javascript", "lua"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderIssue {
+    pub row: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default)]
+struct DescriptionLine {
+    text: String,
+    blank_before: bool,
+    verbatim: bool,
+}
+
+/// The `#VRML_SIM` line and the structured comments that follow it.
+#[derive(Debug, Clone, Default)]
+pub struct Header {
+    pub version: Option<String>,
+    pub encoding: Option<String>,
+    pub template_language: Option<String>,
+    pub license: Option<String>,
+    pub license_url: Option<String>,
+    pub documentation_url: Option<String>,
+    pub tags: Vec<String>,
+    pub keywords: Vec<String>,
+    pub issues: Vec<HeaderIssue>,
+    description: Vec<DescriptionLine>,
+    structured: bool,
+}
+
+impl Header {
+    /// Parses the comment lines at the top of a file, stopping at the first line that is neither
+    /// a comment nor blank.
+    pub fn from_source(code: &str) -> Header {
+        let lines = code
+            .lines()
+            .enumerate()
+            .take_while(|(_, line)| line.trim().is_empty() || line.trim_start().starts_with('#'))
+            .filter(|(_, line)| !line.trim().is_empty());
+        Header::parse(lines)
+    }
+
+    /// Parses a block of comments given as `(row, text)` pairs, rows being zero-based.
+    pub fn parse<'a>(comments: impl IntoIterator<Item = (usize, &'a str)>) -> Header {
+        let mut header = Header::default();
+        let mut first_row = None;
+        let mut last_row = None;
+        for (row, text) in comments {
+            let text = text.trim();
+            let blank_before = last_row.is_some_and(|last| row > last + 1);
+            let first = first_row.is_none();
+            first_row.get_or_insert(row);
+            last_row = Some(row);
+
+            if text.starts_with("#VRML_SIM") {
+                header.parse_version(row, text, first);
+                continue;
+            } else if text.starts_with("#VRML") {
+                header.issue(row, format!("unsupported header \"{text}\"").as_str());
+                header.description.push(DescriptionLine {
+                    text: text.to_string(),
+                    blank_before,
+                    verbatim: true,
+                });
+                continue;
+            }
+
+            if !text.starts_with("##") {
+                let line = text.trim_start_matches('#').trim();
+                if let Some((key, value)) = line.split_once(':') {
+                    let key = key.trim().to_lowercase();
+                    if HEADER_KEYS.contains(&key.as_str()) {
+                        header.parse_key(row, key.as_str(), value.trim());
+                        continue;
+                    }
+                }
+            }
+
+            header.description.push(DescriptionLine {
+                text: text.to_string(),
+                blank_before,
+                verbatim: text.starts_with("##"),
+            });
+        }
+
+        if header.version.is_none() {
+            header.issues.push(HeaderIssue {
+                row: first_row.unwrap_or(0),
+                message: "missing #VRML_SIM header".to_string(),
+            });
+        }
+
+        header
+    }
+
+    fn parse_version(&mut self, row: usize, text: &str, first: bool) {
+        self.structured = true;
+        if self.version.is_some() {
+            self.issue(row, "duplicated #VRML_SIM header");
+            return;
+        }
+        if !first {
+            self.issue(row, "#VRML_SIM header must be the first line of the file");
+        }
+
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        if tokens.first() != Some(&"#VRML_SIM")
+            || tokens.len() != 3
+            || !is_release(tokens[1])
+            || tokens[2] != "utf8"
+        {
+            self.issue(
+                row,
+                format!("malformed header \"{text}\", expected \"#VRML_SIM R2023b utf8\"").as_str(),
+            );
+        }
+
+        let rest = text.trim_start_matches("#VRML_SIM");
+        let mut tokens = rest.split_whitespace();
+        self.version = Some(tokens.next().unwrap_or_default().to_string());
+        let encoding: Vec<&str> = tokens.collect();
+        self.encoding = (!encoding.is_empty()).then(|| encoding.join(" "));
+    }
+
+    fn parse_key(&mut self, row: usize, key: &str, value: &str) {
+        self.structured = true;
+        let slot = match key {
+            "template language" => &mut self.template_language,
+            "license" => &mut self.license,
+            "license url" => &mut self.license_url,
+            "documentation url" => &mut self.documentation_url,
+            "tags" => {
+                for tag in split_list(value) {
+                    if !KNOWN_TAGS.contains(&tag.as_str()) {
+                        self.issue(row, format!("unknown tag \"{tag}\"").as_str());
+                    }
+                    self.tags.push(tag);
+                }
+                return;
+            }
+            _ => {
+                self.keywords.extend(split_list(value));
+                return;
+            }
+        };
+
+        if slot.is_some() {
+            let message = format!("duplicated \"# {key}:\" line");
+            self.issue(row, message.as_str());
+            return;
+        }
+        *slot = Some(value.to_string());
+
+        if key == "template language" && !TEMPLATE_LANGUAGES.contains(&value) {
+            self.issue(
+                row,
+                format!("unknown template language \"{value}\"").as_str(),
+            );
+        }
+    }
+
+    fn issue(&mut self, row: usize, message: &str) {
+        self.issues.push(HeaderIssue {
+            row,
+            message: message.to_string(),
+        })
+    }
+
+    /// The release named by the `#VRML_SIM` line, if it is well formed.
+    pub fn release(&self) -> Option<Release> {
+        self.version.as_deref().and_then(Release::parse)
+    }
+
+    /// Whether the block contains a `#VRML_SIM` line or any structured key, that is, whether it
+    /// is a header at all and not just a leading comment.
+    pub fn is_structured(&self) -> bool {
+        self.structured
+    }
+
+    /// The free text comments of the header as written, usually the PROTO description. Empty
+    /// entries stand for blank lines.
+    pub fn description(&self) -> Vec<&str> {
+        let mut lines = Vec::new();
+        for line in &self.description {
+            if line.blank_before {
+                lines.push("");
+            }
+            lines.push(line.text.as_str());
+        }
+        lines
+    }
+
+    /// The description as paragraphs of plain text, split at blank lines, empty comments and
+    /// banners. The text of a banner, like `## Usage ##`, is a paragraph of its own.
+    pub fn paragraphs(&self) -> Vec<String> {
+        let mut paragraphs = Vec::new();
+        let mut paragraph = String::new();
+        for line in &self.description {
+            let banner = line.text.starts_with("##");
+            let text = line.text.trim_start_matches('#').trim();
+            if (line.blank_before || text.is_empty() || banner) && !paragraph.is_empty() {
+                paragraphs.push(std::mem::take(&mut paragraph));
+            }
+            if banner {
+                let title = text.trim_end_matches('#').trim();
+                if !title.is_empty() {
+                    paragraphs.push(title.to_string());
+                }
+                continue;
+            }
+            if !text.is_empty() {
+                if !paragraph.is_empty() {
+                    paragraph.push(' ');
+                }
+                paragraph += text;
+            }
+        }
+        if !paragraph.is_empty() {
+            paragraphs.push(paragraph);
+        }
+        paragraphs
+    }
+
+    /// The normalized header, one comment per entry. Empty entries stand for blank lines.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(version) = &self.version {
+            let encoding = self.encoding.as_deref().unwrap_or_default();
+            lines.push(
+                format!("#VRML_SIM {version} {encoding}")
+                    .trim_end()
+                    .to_string(),
+            );
+        }
+
+        let values = [
+            self.template_language.clone(),
+            self.license.clone(),
+            self.license_url.clone(),
+            self.documentation_url.clone(),
+            (!self.tags.is_empty()).then(|| self.tags.join(", ")),
+            (!self.keywords.is_empty()).then(|| self.keywords.join(", ")),
+        ];
+        for (key, value) in HEADER_KEYS.iter().zip(values) {
+            if let Some(value) = value {
+                lines.push(format!("# {key}: {value}").trim_end().to_string());
+            }
+        }
+
+        for line in &self.description {
+            if line.blank_before {
+                lines.push(String::new());
+            }
+            if line.verbatim {
+                lines.push(line.text.clone());
+            } else {
+                let text = line.text.trim_start_matches('#').trim();
+                lines.push(format!("# {text}").trim_end().to_string());
+            }
+        }
+
+        lines
+    }
+}
+
+/// Whether `text` is a Webots release name, like `R2023b`.
+pub fn is_release(text: &str) -> bool {
+    Release::parse(text).is_some()
+}
+
+/// A Webots release, like `R2023b`. Releases order chronologically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Release {
+    pub year: u16,
+    pub revision: char,
+}
+
+impl Release {
+    pub fn parse(text: &str) -> Option<Release> {
+        let bytes = text.as_bytes();
+        let valid = bytes.len() == 6
+            && bytes[0] == b'R'
+            && bytes[1..5].iter().all(u8::is_ascii_digit)
+            && matches!(bytes[5], b'a' | b'b');
+        valid.then(|| Release {
+            year: text[1..5].parse().unwrap_or_default(),
+            revision: bytes[5] as char,
+        })
+    }
+}
+
+impl fmt::Display for Release {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "R{}{}", self.year, self.revision)
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(header: &Header) -> Vec<&str> {
+        header
+            .issues
+            .iter()
+            .map(|issue| issue.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn parses_version_and_keys() {
+        let header = Header::from_source(
+            "#VRML_SIM R2023b utf8\n\
+             # license: Apache License 2.0\n\
+             # tags: nonDeterministic, hidden\n\
+             # keywords: robot/wheeled\n\
+             # template language: javascript\n\
+             # A robot.\n\
+             PROTO Robot [\n\
+             ]\n",
+        );
+        assert_eq!(header.version.as_deref(), Some("R2023b"));
+        assert_eq!(header.encoding.as_deref(), Some("utf8"));
+        assert_eq!(header.release(), Release::parse("R2023b"));
+        assert_eq!(header.license.as_deref(), Some("Apache License 2.0"));
+        assert_eq!(header.template_language.as_deref(), Some("javascript"));
+        assert_eq!(header.tags, ["nonDeterministic", "hidden"]);
+        assert_eq!(header.keywords, ["robot/wheeled"]);
+        assert_eq!(header.description(), ["# A robot."]);
+        assert!(header.is_structured());
+        assert!(header.issues.is_empty(), "{:?}", header.issues);
+    }
+
+    #[test]
+    fn lines_are_normalized_in_key_order() {
+        let header = Header::from_source(
+            "#VRML_SIM   R2023b   utf8\n\
+             #tags:hidden\n\
+             #   License: MIT\n\
+             #A robot.\n\
+             \n\
+             ## Banner ##\n",
+        );
+        assert_eq!(
+            header.lines(),
+            [
+                "#VRML_SIM R2023b utf8",
+                "# license: MIT",
+                "# tags: hidden",
+                "# A robot.",
+                "",
+                "## Banner ##",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_malformed_headers() {
+        let header = Header::from_source("# A comment.\n#VRML_SIM 2023 utf8\n");
+        assert_eq!(
+            messages(&header),
+            [
+                "#VRML_SIM header must be the first line of the file",
+                "malformed header \"#VRML_SIM 2023 utf8\", expected \"#VRML_SIM R2023b utf8\"",
+            ]
+        );
+        assert_eq!(header.issues[0].row, 1);
+        assert_eq!(header.release(), None);
+
+        let header = Header::from_source("#VRML_SIM R2023b utf8\n#VRML_SIM R2023a utf8\n");
+        assert_eq!(messages(&header), ["duplicated #VRML_SIM header"]);
+
+        let header = Header::from_source("#VRML V2.0 utf8\n");
+        assert_eq!(
+            messages(&header),
+            [
+                "unsupported header \"#VRML V2.0 utf8\"",
+                "missing #VRML_SIM header"
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_values_and_duplicated_keys() {
+        let header = Header::from_source(
+            "#VRML_SIM R2023b utf8\n\
+             # tags: hidden, fancy\n\
+             # template language: python\n\
+             # license: MIT\n\
+             # license: BSD\n",
+        );
+        assert_eq!(
+            messages(&header),
+            [
+                "unknown tag \"fancy\"",
+                "unknown template language \"python\"",
+                "duplicated \"# license:\" line",
+            ]
+        );
+        assert_eq!(header.license.as_deref(), Some("MIT"));
+    }
+
+    #[test]
+    fn comments_without_keys_are_not_a_header() {
+        let header = Header::from_source("# Just a comment.\nSolid {\n}\n");
+        assert!(!header.is_structured());
+        assert_eq!(messages(&header), ["missing #VRML_SIM header"]);
+    }
+
+    #[test]
+    fn releases_parse_and_order() {
+        let r2022b = Release::parse("R2022b").unwrap();
+        let r2023a = Release::parse("R2023a").unwrap();
+        assert!(r2022b < r2023a);
+        assert_eq!(r2023a.to_string(), "R2023a");
+        for invalid in ["R2023", "R2023c", "2023a", "R20x3a", "R2023a1"] {
+            assert!(!is_release(invalid), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn paragraphs_split_at_blank_lines_empty_comments_and_banners() {
+        let header = Header::from_source(
+            "#VRML_SIM R2023b utf8\n\
+             # A wall\n\
+             # made of bricks.\n\
+             #\n\
+             # Second paragraph.\n\
+             ##########\n\
+             # After a banner.\n\
+             ## Usage ##\n\
+             # Set the size.\n\
+             \n\
+             # Last.\n",
+        );
+        assert_eq!(
+            header.paragraphs(),
+            [
+                "A wall made of bricks.",
+                "Second paragraph.",
+                "After a banner.",
+                "Usage",
+                "Set the size.",
+                "Last.",
+            ]
+        );
+    }
+}
@@ -0,0 +1,273 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::HashSet;
+
+use tree_sitter::Node;
+
+use super::lint::{Context, Report, Rule, Severity};
+use super::schema::field_type;
+use super::syntax::{parent_node_type, FieldDecl, Property};
+
+/// The interface of a `proto` node, in declaration order.
+pub fn interface(proto: Node) -> Vec<FieldDecl> {
+    let mut cursor = proto.walk();
+    let fields: Vec<Node> = proto
+        .named_children(&mut cursor)
+        .filter(|n| n.kind() == "field")
+        .collect();
+    fields
+        .into_iter()
+        .filter_map(|f| FieldDecl::new(f).ok())
+        .collect()
+}
+
+/// Every `proto` node in the tree. There is at most one per file, but nothing stops a world
+/// from being parsed as one.
+pub fn protos(root: Node) -> Vec<Node> {
+    let mut cursor = root.walk();
+    let protos: Vec<Node> = root
+        .children(&mut cursor)
+        .filter(|n| n.kind() == "proto")
+        .collect();
+    protos
+}
+
+/// The `field IS name` properties of a PROTO body.
+pub fn is_properties(proto: Node) -> Vec<Property> {
+    let mut properties = Vec::new();
+    let mut cursor = proto.walk();
+    for child in proto.children(&mut cursor) {
+        if child.kind() != "field" {
+            collect_is(child, &mut properties);
+        }
+    }
+    properties
+}
+
+fn collect_is<'a>(node: Node<'a>, properties: &mut Vec<Property<'a>>) {
+    if node.kind() == "property" {
+        if let Some(property) = Property::new(node).filter(|p| p.is_target.is_some()) {
+            properties.push(property);
+        }
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_is(child, properties);
+    }
+}
+
+/// Names read as `fields.name` by the template code of a PROTO.
+pub fn template_references<'a>(proto: Node, code: &'a str) -> HashSet<&'a str> {
+    template_field_references(proto, code)
+        .into_iter()
+        .map(|(_, name)| name)
+        .collect()
+}
+
+/// Every `fields.name` read by the template code of a PROTO, with the byte offset of the name.
+pub fn template_field_references<'a>(proto: Node, code: &'a str) -> Vec<(usize, &'a str)> {
+    let mut references = Vec::new();
+    let mut stack = vec![proto];
+    while let Some(node) = stack.pop() {
+        if matches!(node.kind(), "javascript_block" | "javascript_expression") {
+            let text = node.utf8_text(code.as_bytes()).unwrap_or_default();
+            for (i, _) in text.match_indices("fields.") {
+                let before = text[..i].chars().next_back();
+                if before.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '.') {
+                    continue;
+                }
+                let start = i + "fields.".len();
+                let rest = &text[start..];
+                let end = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                references.push((node.start_byte() + start, &rest[..end]));
+            }
+            continue;
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor));
+    }
+    references.sort();
+    references
+}
+
+/// `IS` targets that are not declared in the PROTO interface.
+pub struct UnknownIsTarget;
+
+impl Rule for UnknownIsTarget {
+    fn id(&self) -> &'static str {
+        "unknown-is"
+    }
+
+    fn description(&self) -> &'static str {
+        "IS refers to a field that is not in the PROTO interface"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, context: &Context, report: &mut Report) {
+        for proto in protos(context.root) {
+            let interface = interface(proto);
+            for property in is_properties(proto) {
+                let target = property.is_target.unwrap();
+                let name = context.text(target);
+                if !interface.iter().any(|f| context.text(f.name) == name) {
+                    report.add(target.range(), format!("{name} is not an interface field"));
+                }
+            }
+        }
+    }
+}
+
+/// `IS` binding a node field to an interface field of another type.
+pub struct IsTypeMismatch;
+
+impl Rule for IsTypeMismatch {
+    fn id(&self) -> &'static str {
+        "is-type-mismatch"
+    }
+
+    fn description(&self) -> &'static str {
+        "IS binds fields of different types"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, context: &Context, report: &mut Report) {
+        for proto in protos(context.root) {
+            let interface = interface(proto);
+            for property in is_properties(proto) {
+                let target = property.is_target.unwrap();
+                let Some(declaration) = interface
+                    .iter()
+                    .find(|f| context.text(f.name) == context.text(target))
+                else {
+                    continue;
+                };
+                let Some(node_type) = property.name.parent().and_then(parent_node_type) else {
+                    continue;
+                };
+                let field = context.text(property.name);
+                let Some(expected) = field_type(context.text(node_type), field, context.release)
+                else {
+                    continue;
+                };
+                let actual = context.text(declaration.field_type);
+                if expected != actual {
+                    let message = format!(
+                        "{}.{field} is {expected} but {} is {actual}",
+                        context.text(node_type),
+                        context.text(target)
+                    );
+                    report
+                        .add(target.range(), message)
+                        .related
+                        .push((declaration.name.range(), "declared here".to_string()));
+                }
+            }
+        }
+    }
+}
+
+/// Interface fields neither bound with `IS` nor read by the template.
+pub struct UnusedField;
+
+impl Rule for UnusedField {
+    fn id(&self) -> &'static str {
+        "unused-field"
+    }
+
+    fn description(&self) -> &'static str {
+        "PROTO interface field not referenced by IS or by the template"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, context: &Context, report: &mut Report) {
+        for proto in protos(context.root) {
+            let mut used = template_references(proto, context.code);
+            for property in is_properties(proto) {
+                used.insert(context.text(property.is_target.unwrap()));
+            }
+            for field in interface(proto) {
+                let name = context.text(field.name);
+                if !used.contains(name) {
+                    report.add(field.name.range(), format!("field {name} is never used"));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lint::tests::{check, messages};
+    use crate::syntax::parse;
+
+    const LAMP: &str = r#"#VRML_SIM R2023b utf8
+PROTO Lamp [
+  field SFVec3f  translation 0 0 0
+  field SFFloat  name 1
+  field SFString unused ""
+  field SFColor  tint 1 1 1
+]
+{
+  Solid {
+    translation IS translation
+    name IS name
+    description IS missing
+    %< const tint = fields.tint.value; >%
+  }
+}
+"#;
+
+    #[test]
+    fn unknown_is_target() {
+        assert_eq!(
+            messages("unknown-is", LAMP),
+            [(12, "missing is not an interface field".to_string())]
+        );
+    }
+
+    #[test]
+    fn is_type_mismatch() {
+        assert_eq!(
+            messages("is-type-mismatch", LAMP),
+            [(11, "Solid.name is SFString but name is SFFloat".to_string())]
+        );
+        let related = &check("is-type-mismatch", LAMP)[0].related;
+        assert_eq!(related[0].0.start_point.row + 1, 4);
+    }
+
+    #[test]
+    fn unused_field() {
+        assert_eq!(
+            messages("unused-field", LAMP),
+            [(5, "field unused is never used".to_string())]
+        );
+    }
+
+    #[test]
+    fn template_field_references_skip_other_objects() {
+        let code = "PROTO P [\n]\n{\n  %< const a = fields.size.value + other.fields.x + myfields.y; >%\n}\n";
+        let tree = parse(code).unwrap();
+        let proto = protos(tree.root_node())[0];
+        let references = template_field_references(proto, code);
+        assert_eq!(references.len(), 1);
+        let (start, name) = references[0];
+        assert_eq!(name, "size");
+        assert_eq!(&code[start..start + 4], "size");
+    }
+}
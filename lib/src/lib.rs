@@ -5,7 +5,49 @@
  */
 
 mod args;
+mod ast;
 mod beautifier;
+mod config;
+mod defaults;
+mod defuse;
+mod doc;
+mod edit;
+mod externproto;
+mod find;
+mod generate;
+mod header;
+mod interface;
+mod lint;
+mod minify;
+mod query;
+mod refactor;
+mod schema;
+mod syntax;
+mod template;
+#[cfg(test)]
+mod testing;
+mod types;
+mod upgrade;
 
 pub use beautifier::*;
 pub use args::*;
+pub use ast::*;
+pub use config::*;
+pub use defaults::*;
+pub use defuse::*;
+pub use doc::*;
+pub use edit::*;
+pub use externproto::*;
+pub use find::*;
+pub use generate::*;
+pub use header::*;
+pub use interface::*;
+pub use lint::*;
+pub use minify::*;
+pub use query::*;
+pub use refactor::*;
+pub use schema::*;
+pub use syntax::*;
+pub use template::*;
+pub use types::*;
+pub use upgrade::*;
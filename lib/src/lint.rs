@@ -0,0 +1,366 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use tree_sitter::{Node, Point, Range};
+
+use super::config::Config;
+use super::defuse::{DuplicateDef, UnusedDef, UseBeforeDef};
+use super::edit::Edit;
+use super::externproto::{MissingExternProto, UnresolvedExternProto, UnusedExternProto};
+use super::header::{Header, Release};
+use super::interface::{IsTypeMismatch, UnknownIsTarget, UnusedField};
+use super::schema::UnknownField;
+use super::syntax::{find_first_error_node, parse};
+use super::types::ValueType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Off,
+    Info,
+    Warning,
+    Error,
+}
+
+impl FromStr for Severity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Severity> {
+        match s {
+            "off" => Ok(Severity::Off),
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            _ => Err(anyhow!(
+                "Unknown severity \"{s}\", expected off, info, warning or error"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Severity::Off => "off",
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        f.write_str(text)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub range: Range,
+    pub message: String,
+    /// Other places involved in the violation, with a note for each.
+    pub related: Vec<(Range, String)>,
+    /// Edits that correct the violation, if it can be fixed automatically.
+    pub fix: Vec<Edit>,
+}
+
+/// What rules get to look at: the source, its syntax tree and the file it came from, if any.
+pub struct Context<'a> {
+    pub code: &'a str,
+    pub root: Node<'a>,
+    pub path: Option<&'a Path>,
+    pub config: &'a Config,
+    /// The release of the `#VRML_SIM` header, which the built-in nodes are checked against.
+    pub release: Option<Release>,
+}
+
+impl<'a> Context<'a> {
+    pub fn text(&self, node: Node) -> &'a str {
+        node.utf8_text(self.code.as_bytes()).unwrap_or_default()
+    }
+}
+
+/// Collects the violations found by a rule, tagging them with its id and severity.
+pub struct Report<'r> {
+    rule: &'static str,
+    severity: Severity,
+    diagnostics: &'r mut Vec<Diagnostic>,
+}
+
+impl Report<'_> {
+    pub fn add(&mut self, range: Range, message: impl Into<String>) -> &mut Diagnostic {
+        self.diagnostics.push(Diagnostic {
+            rule: self.rule,
+            severity: self.severity,
+            range,
+            message: message.into(),
+            related: Vec::new(),
+            fix: Vec::new(),
+        });
+        self.diagnostics.last_mut().unwrap()
+    }
+}
+
+pub trait Rule {
+    /// Identifier used on the command line and in the configuration file.
+    fn id(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    fn default_severity(&self) -> Severity;
+    fn check(&self, context: &Context, report: &mut Report);
+}
+
+/// All known rules, in the order they run.
+pub fn rules() -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(HeaderRule),
+        Box::new(UseBeforeDef),
+        Box::new(DuplicateDef),
+        Box::new(UnusedDef),
+        Box::new(UnknownIsTarget),
+        Box::new(IsTypeMismatch),
+        Box::new(UnusedField),
+        Box::new(UnknownField),
+        Box::new(ValueType),
+        Box::new(UnresolvedExternProto),
+        Box::new(MissingExternProto),
+        Box::new(UnusedExternProto),
+    ]
+}
+
+/// Effective severity of each rule: the rule default, overridden by the configuration file and
+/// then by `overrides`, given as `rule=level` strings.
+pub fn severities(
+    config: &Config,
+    overrides: &[String],
+) -> Result<HashMap<&'static str, Severity>> {
+    let rules = rules();
+    let mut severities: HashMap<&'static str, Severity> = rules
+        .iter()
+        .map(|rule| (rule.id(), rule.default_severity()))
+        .collect();
+
+    let overrides = overrides
+        .iter()
+        .map(|text| {
+            let (id, level) = text
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid rule \"{text}\", expected RULE=LEVEL"))?;
+            Ok((id.trim().to_string(), level.trim().parse::<Severity>()?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for (id, severity) in config
+        .rules
+        .iter()
+        .map(|(k, v)| (k.clone(), *v))
+        .chain(overrides)
+    {
+        let id = rules
+            .iter()
+            .map(|rule| rule.id())
+            .find(|rule| *rule == id)
+            .ok_or_else(|| anyhow!("Unknown rule \"{id}\""))?;
+        severities.insert(id, severity);
+    }
+
+    Ok(severities)
+}
+
+/// Runs every enabled rule over `code` and returns the violations sorted by position. Syntax
+/// errors are always reported and stop the analysis.
+pub fn lint(
+    code: &str,
+    path: Option<&Path>,
+    config: &Config,
+    severities: &HashMap<&'static str, Severity>,
+) -> Result<Vec<Diagnostic>> {
+    let tree = parse(code)?;
+    let root = tree.root_node();
+    let mut diagnostics = Vec::new();
+
+    if root.has_error() {
+        let error_node = find_first_error_node(root).unwrap_or(root);
+        diagnostics.push(Diagnostic {
+            rule: "syntax",
+            severity: Severity::Error,
+            range: error_node.range(),
+            message: "syntax error".to_string(),
+            related: Vec::new(),
+            fix: Vec::new(),
+        });
+        return Ok(diagnostics);
+    }
+
+    let context = Context {
+        code,
+        root,
+        path,
+        config,
+        release: Header::from_source(code).release(),
+    };
+    for rule in rules() {
+        let severity = severities
+            .get(rule.id())
+            .copied()
+            .unwrap_or(rule.default_severity());
+        if severity == Severity::Off {
+            continue;
+        }
+        let mut report = Report {
+            rule: rule.id(),
+            severity,
+            diagnostics: &mut diagnostics,
+        };
+        rule.check(&context, &mut report);
+    }
+
+    diagnostics.sort_by_key(|d| (d.range.start_point, d.rule));
+    Ok(diagnostics)
+}
+
+/// The range covering a whole line, for diagnostics that do not come from a node.
+pub fn line_range(code: &str, row: usize) -> Range {
+    let start_byte: usize = code.split_inclusive('\n').take(row).map(str::len).sum();
+    let line = code[start_byte..].lines().next().unwrap_or_default();
+    Range {
+        start_byte,
+        end_byte: start_byte + line.len(),
+        start_point: Point { row, column: 0 },
+        end_point: Point {
+            row,
+            column: line.len(),
+        },
+    }
+}
+
+struct HeaderRule;
+
+impl Rule for HeaderRule {
+    fn id(&self) -> &'static str {
+        "header"
+    }
+
+    fn description(&self) -> &'static str {
+        "missing or malformed #VRML_SIM header, unknown tags and template languages"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, context: &Context, report: &mut Report) {
+        for issue in Header::from_source(context.code).issues {
+            report.add(line_range(context.code, issue.row), issue.message);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::edit::apply_edits;
+
+    /// The diagnostics of the rule `id` alone over `code`, with the default configuration.
+    pub fn check(id: &str, code: &str) -> Vec<Diagnostic> {
+        check_at(id, code, None)
+    }
+
+    /// As `check`, for `code` read from `path`.
+    pub fn check_at(id: &str, code: &str, path: Option<&Path>) -> Vec<Diagnostic> {
+        let severities = rules()
+            .iter()
+            .map(|rule| match rule.id() {
+                rule_id if rule_id == id => (rule_id, Severity::Error),
+                rule_id => (rule_id, Severity::Off),
+            })
+            .collect();
+        lint(code, path, &Config::default(), &severities).unwrap()
+    }
+
+    /// The 1-based line and message of the diagnostics of the rule `id` over `code`.
+    pub fn messages(id: &str, code: &str) -> Vec<(usize, String)> {
+        check(id, code)
+            .into_iter()
+            .map(|d| (d.range.start_point.row + 1, d.message))
+            .collect()
+    }
+
+    /// `code` with the fixes of the rule `id` applied.
+    pub fn fix(id: &str, code: &str) -> String {
+        fix_at(id, code, None)
+    }
+
+    /// As `fix`, for `code` read from `path`.
+    pub fn fix_at(id: &str, code: &str, path: Option<&Path>) -> String {
+        let edits: Vec<Edit> = check_at(id, code, path)
+            .into_iter()
+            .flat_map(|d| d.fix)
+            .collect();
+        apply_edits(code, &edits)
+    }
+
+    #[test]
+    fn severities_default_then_config_then_overrides() {
+        let defaults = severities(&Config::default(), &[]).unwrap();
+        assert_eq!(defaults.len(), rules().len());
+        assert_eq!(defaults["header"], Severity::Warning);
+
+        let mut config = Config::default();
+        config.rules.insert("header".to_string(), Severity::Error);
+        config.rules.insert("unused-def".to_string(), Severity::Off);
+        let overrides = ["header = info".to_string()];
+        let severities = severities(&config, &overrides).unwrap();
+        assert_eq!(severities["header"], Severity::Info);
+        assert_eq!(severities["unused-def"], Severity::Off);
+    }
+
+    #[test]
+    fn severities_reject_unknown_rules_and_levels() {
+        let config = Config::default();
+        assert!(severities(&config, &["nope=off".to_string()]).is_err());
+        assert!(severities(&config, &["header=loud".to_string()]).is_err());
+        assert!(severities(&config, &["header".to_string()]).is_err());
+        assert_eq!("warning".parse::<Severity>().unwrap(), Severity::Warning);
+        assert_eq!(Severity::Info.to_string(), "info");
+    }
+
+    #[test]
+    fn line_range_covers_the_line() {
+        let range = line_range("a\nbcd\ne", 1);
+        assert_eq!((range.start_byte, range.end_byte), (2, 5));
+        assert_eq!(range.end_point.column, 3);
+    }
+
+    #[test]
+    fn header_rule_reports_header_issues() {
+        let code = "#VRML_SIM R2023b utf8\n# tags: shiny\nSolid {\n}\n";
+        assert_eq!(
+            messages("header", code),
+            [(2, "unknown tag \"shiny\"".to_string())]
+        );
+        assert!(messages("header", "#VRML_SIM R2023b utf8\nSolid {\n}\n").is_empty());
+    }
+
+    #[test]
+    fn syntax_errors_stop_the_analysis() {
+        let diagnostics = check("header", "Solid {\n  name \"x\"\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].rule, "syntax");
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn disabled_rules_do_not_run() {
+        let severities = HashMap::from([("header", Severity::Off)]);
+        let diagnostics = lint("Solid {\n}\n", None, &Config::default(), &severities).unwrap();
+        assert!(diagnostics.iter().all(|d| d.rule != "header"));
+    }
+}
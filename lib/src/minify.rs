@@ -0,0 +1,191 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use anyhow::Result;
+use tree_sitter::Node;
+
+use super::header::Header;
+use super::syntax::{parse_valid, text};
+
+/// Removes the comments, except for the header, and the indentation and alignment of `code`.
+/// Tokens keep the lines they are on, only losing the blank lines between them, as the formatter
+/// keeps nodes and lists written on one line as they are. Template code is copied as is.
+pub fn minify(code: &str) -> Result<String> {
+    let tree = parse_valid(code)?;
+    let root = tree.root_node();
+
+    let mut cursor = root.walk();
+    let children: Vec<Node> = root.children(&mut cursor).collect();
+    let header_len = children
+        .iter()
+        .take_while(|child| child.kind() == "comment")
+        .count();
+    let header = Header::parse(
+        children[..header_len]
+            .iter()
+            .map(|child| (child.start_position().row, text(*child, code))),
+    );
+
+    let mut minifier = Minifier {
+        code,
+        minified: String::with_capacity(code.len()),
+        row: None,
+    };
+    if header.is_structured() {
+        for comment in &children[..header_len] {
+            minifier.token(*comment, true);
+        }
+    }
+    for child in &children[header_len..] {
+        minifier.node(*child, true);
+    }
+    minifier.minified.push('\n');
+    Ok(minifier.minified)
+}
+
+struct Minifier<'a> {
+    code: &'a str,
+    minified: String,
+    /// The row the last token ends on, if any was written.
+    row: Option<usize>,
+}
+
+impl Minifier<'_> {
+    fn node(&mut self, node: Node, top_level: bool) {
+        match node.kind() {
+            "comment" => {}
+            "javascript_block" | "javascript_expression" => self.token(node, top_level),
+            _ if node.child_count() == 0 || text(node, self.code).starts_with('"') => {
+                self.token(node, top_level)
+            }
+            _ => {
+                let mut cursor = node.walk();
+                for (i, child) in node.children(&mut cursor).enumerate() {
+                    self.node(child, top_level && i == 0);
+                }
+            }
+        }
+    }
+
+    /// Writes `node` as is, on a new line if it starts on a line of its own. Blank lines are only
+    /// kept between top-level nodes, where the formatter keeps them.
+    fn token(&mut self, node: Node, top_level: bool) {
+        let text = text(node, self.code);
+        let start = node.start_position().row;
+        match self.row {
+            Some(row) if start > row + 1 && top_level => self.minified += "\n\n",
+            Some(row) if start > row => self.minified.push('\n'),
+            Some(_) => {
+                let last = self.minified.chars().next_back();
+                let first = text.chars().next();
+                let tight = |c: Option<char>| c.is_some_and(|c| "[]{},".contains(c));
+                if !tight(last) && !tight(first) {
+                    self.minified.push(' ');
+                }
+            }
+            None => {}
+        }
+        self.minified += text;
+        self.row = Some(node.end_position().row);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::Arguments;
+    use crate::beautifier::beautify;
+    use clap::Parser;
+
+    fn format(code: &str) -> String {
+        let mut arguments = Arguments::parse_from(["wbproto-beautifier", "--inplace"]);
+        beautify(code, &mut arguments).unwrap()
+    }
+
+    const LAMP: &str = r#"#VRML_SIM R2023b utf8
+# license: Apache License 2.0
+# A lamp.
+
+EXTERNPROTO "Bulb.proto"
+
+PROTO Lamp [
+  field SFFloat   power  1   # The power.
+  # Colors.
+  field MFColor   colors [ 1 1 1, 0 0 0 ]
+]
+{
+  %< const p = fields.power.value; >%
+  Solid {
+
+    translation 0 0 %<= p >%   # Height.
+    children [ Bulb { } ]
+    name   "a  # b"
+  }
+}
+"#;
+
+    const LAMP_WITHOUT_COMMENTS: &str = r#"#VRML_SIM R2023b utf8
+# license: Apache License 2.0
+# A lamp.
+
+EXTERNPROTO "Bulb.proto"
+
+PROTO Lamp [
+  field SFFloat   power  1
+  field MFColor   colors [ 1 1 1, 0 0 0 ]
+]
+{
+  %< const p = fields.power.value; >%
+  Solid {
+
+    translation 0 0 %<= p >%
+    children [ Bulb { } ]
+    name   "a  # b"
+  }
+}
+"#;
+
+    #[test]
+    fn minified_files_format_as_the_original_without_comments() {
+        let minified = minify(LAMP).unwrap();
+        assert_eq!(format(&minified), format(LAMP_WITHOUT_COMMENTS));
+    }
+
+    #[test]
+    fn minifying_is_idempotent() {
+        let minified = minify(LAMP).unwrap();
+        assert_eq!(minify(&minified).unwrap(), minified);
+        let mut arguments = Arguments::parse_from(["wbproto-beautifier", "--inplace", "--minify"]);
+        assert_eq!(beautify(LAMP, &mut arguments).unwrap(), minified);
+    }
+
+    #[test]
+    fn header_and_template_code_are_kept() {
+        let minified = minify(LAMP).unwrap();
+        assert!(minified
+            .starts_with("#VRML_SIM R2023b utf8\n# license: Apache License 2.0\n# A lamp.\n"));
+        assert!(!minified.contains("# The power."));
+        assert!(!minified.contains("# Colors."));
+        assert!(!minified.contains("# Height."));
+        assert!(minified.contains("%< const p = fields.power.value; >%"));
+        assert!(minified.contains("\"a  # b\""));
+        assert!(minified.lines().all(|line| !line.starts_with(' ')));
+        assert!(!minified.contains("\n\n\n"));
+    }
+
+    #[test]
+    fn files_without_header_lose_their_comments() {
+        let minified = minify("# Not a header.\nSolid {\n  name \"a\"\n}\n").unwrap();
+        assert!(!minified.contains('#'));
+        assert_eq!(minified.lines().count(), 3);
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        let error = minify("Solid {\n  name \"a\"\n").unwrap_err();
+        assert!(error.to_string().starts_with("Parsed file contain errors"));
+    }
+}
@@ -0,0 +1,183 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use tree_sitter::{Query, QueryCursor};
+
+use super::syntax::parse;
+
+/// A match of a query: the pattern it matched, 0-based in the order of the query, and its captures.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryMatch {
+    pub pattern: usize,
+    pub captures: Vec<QueryCapture>,
+}
+
+/// A node captured by a match, with its 1-based position.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryCapture {
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+/// Compiles a tree-sitter S-expression query over the PROTO grammar, as in
+/// `(property . (_) @name (#eq? @name "physics"))`.
+pub fn compile_query(source: &str) -> Result<Query> {
+    Query::new(&tree_sitter_wbproto::language(), source)
+        .map_err(|err| anyhow!("Invalid query (at line {}): {err}", err.row + 1))
+}
+
+/// The matches of `query` in `code`, in the order they appear. Predicates like `#eq?` and
+/// `#match?` are applied. Files with syntax errors are searched as far as they parse.
+pub fn query_matches(query: &Query, code: &str) -> Result<Vec<QueryMatch>> {
+    let tree = parse(code)?;
+    let names = query.capture_names();
+    let mut cursor = QueryCursor::new();
+    let matches = cursor
+        .matches(query, tree.root_node(), code.as_bytes())
+        .map(|m| QueryMatch {
+            pattern: m.pattern_index,
+            captures: m
+                .captures
+                .iter()
+                .map(|capture| {
+                    let position = capture.node.start_position();
+                    QueryCapture {
+                        name: names[capture.index as usize].to_string(),
+                        line: position.row + 1,
+                        column: position.column + 1,
+                        text: capture
+                            .node
+                            .utf8_text(code.as_bytes())
+                            .unwrap_or_default()
+                            .to_string(),
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+    Ok(matches)
+}
+
+/// The matches of several files as a JSON array, one object per match with the file it is in.
+pub fn query_json(files: &[(String, Vec<QueryMatch>)]) -> Result<String> {
+    #[derive(Serialize)]
+    struct FileMatch<'a> {
+        file: &'a str,
+        #[serde(flatten)]
+        query_match: &'a QueryMatch,
+    }
+
+    let matches: Vec<FileMatch> = files
+        .iter()
+        .flat_map(|(file, matches)| {
+            matches.iter().map(|query_match| FileMatch {
+                file: file.as_str(),
+                query_match,
+            })
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&matches)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORLD: &str = r#"#VRML_SIM R2023b utf8
+DEF BOX Solid {
+  physics Physics {
+  }
+}
+Solid {
+  name "wall"
+}
+"#;
+
+    fn captures(query: &str, code: &str) -> Vec<(usize, String, usize, usize, String)> {
+        let query = compile_query(query).unwrap();
+        query_matches(&query, code)
+            .unwrap()
+            .into_iter()
+            .flat_map(|m| {
+                m.captures
+                    .into_iter()
+                    .map(move |c| (m.pattern, c.name, c.line, c.column, c.text))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn invalid_queries_are_reported() {
+        let error = compile_query("(node\n  (unknown_kind) @x)").unwrap_err();
+        assert!(
+            error.to_string().starts_with("Invalid query (at line 2): "),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn matches_apply_predicates() {
+        let query = r#"(property . (_) @field (#eq? @field "physics"))"#;
+        assert_eq!(
+            captures(query, WORLD),
+            [(0, "field".to_string(), 3, 3, "physics".to_string())]
+        );
+    }
+
+    #[test]
+    fn matches_are_in_order_with_their_pattern() {
+        let query = r#"
+(node "DEF" . (_) @def)
+(property . (_) @field (#match? @field "^na"))
+"#;
+        assert_eq!(
+            captures(query, WORLD),
+            [
+                (0, "def".to_string(), 2, 5, "BOX".to_string()),
+                (1, "field".to_string(), 7, 3, "name".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_are_printed_as_json() {
+        let query_match = QueryMatch {
+            pattern: 1,
+            captures: vec![QueryCapture {
+                name: "field".to_string(),
+                line: 3,
+                column: 5,
+                text: "physics".to_string(),
+            }],
+        };
+        let json = query_json(&[
+            ("a.wbt".to_string(), vec![query_match.clone()]),
+            ("b.wbt".to_string(), Vec::new()),
+            ("c.proto".to_string(), vec![query_match]),
+        ])
+        .unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!([
+                {
+                    "file": "a.wbt",
+                    "pattern": 1,
+                    "captures": [{ "name": "field", "line": 3, "column": 5, "text": "physics" }]
+                },
+                {
+                    "file": "c.proto",
+                    "pattern": 1,
+                    "captures": [{ "name": "field", "line": 3, "column": 5, "text": "physics" }]
+                }
+            ])
+        );
+    }
+}
@@ -0,0 +1,400 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use tree_sitter::Node;
+
+use super::defuse::scopes;
+use super::edit::Edit;
+use super::interface::{interface, is_properties, protos, template_field_references};
+use super::syntax::{parse_valid, text, NodeForm, Property};
+
+/// Edits renaming the interface field `from` of the PROTO in `code` to `to`: its declaration, the
+/// `IS` bindings of the body and the `fields.from` reads of the template code. Returns the name of
+/// the PROTO along with the edits.
+pub fn rename_field(code: &str, from: &str, to: &str) -> Result<(String, Vec<Edit>)> {
+    if !is_identifier(to) {
+        return Err(anyhow!("\"{to}\" is not a valid field name."));
+    }
+    let tree = parse_valid(code)?;
+    let root = tree.root_node();
+    let proto = *protos(root)
+        .first()
+        .ok_or_else(|| anyhow!("No PROTO to rename a field of."))?;
+    let name = proto
+        .child_by_field_name("proto")
+        .map(|name| text(name, code))
+        .unwrap_or_default();
+
+    let fields = interface(proto);
+    let field = fields
+        .iter()
+        .find(|field| text(field.name, code) == from)
+        .ok_or_else(|| anyhow!("PROTO {name} has no field {from}."))?;
+    if fields.iter().any(|field| text(field.name, code) == to) {
+        return Err(anyhow!("PROTO {name} already has a field {to}."));
+    }
+
+    let mut edits = vec![replace(field.name, to)];
+    for property in is_properties(proto) {
+        if let Some(target) = property.is_target.filter(|t| text(*t, code) == from) {
+            edits.push(replace(target, to));
+        }
+    }
+    for (start, reference) in template_field_references(proto, code) {
+        if reference == from {
+            edits.push(Edit {
+                start,
+                end: start + from.len(),
+                replacement: to.to_string(),
+            });
+        }
+    }
+    Ok((name.to_string(), edits))
+}
+
+/// Edits renaming the field `from` to `to` where instances of the PROTO `proto` in `code` set it.
+pub fn rename_instance_field(code: &str, proto: &str, from: &str, to: &str) -> Result<Vec<Edit>> {
+    let tree = parse_valid(code)?;
+    let mut edits = Vec::new();
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor));
+        if node.kind() != "property" {
+            continue;
+        }
+        let Some(property) = Property::new(node) else {
+            continue;
+        };
+        let instance = node
+            .parent()
+            .filter(|parent| parent.kind() == "node")
+            .and_then(NodeForm::new)
+            .and_then(|form| form.node_type())
+            .is_some_and(|node_type| text(node_type, code) == proto);
+        if instance && text(property.name, code) == from {
+            edits.push(replace(property.name, to));
+        }
+    }
+    Ok(edits)
+}
+
+/// Edits renaming the `DEF` name `from` to `to`, along with the `USE`s referring to it. If the name
+/// is defined more than once, `line`, 1-based, tells which definition to rename.
+pub fn rename_def(code: &str, from: &str, to: &str, line: Option<usize>) -> Result<Vec<Edit>> {
+    let tree = parse_valid(code)?;
+    let mut defs: Vec<Node> = scopes(tree.root_node(), code)
+        .iter()
+        .flat_map(|scope| scope.defs.clone())
+        .filter(|def| def.name == from)
+        .filter(|def| line.is_none_or(|line| def.identifier.start_position().row + 1 == line))
+        .map(|def| def.identifier)
+        .collect();
+    defs.sort_by_key(|def| def.start_byte());
+    match defs[..] {
+        [def] => rename_def_at(code, def.start_byte(), to),
+        [] => match line {
+            Some(line) => Err(anyhow!("No DEF {from} at line {line}.")),
+            None => Err(anyhow!("No DEF {from}.")),
+        },
+        _ => {
+            let lines: Vec<String> = defs
+                .iter()
+                .map(|def| (def.start_position().row + 1).to_string())
+                .collect();
+            Err(anyhow!(
+                "DEF {from} is defined at lines {}, choose one with its line.",
+                lines.join(", ")
+            ))
+        }
+    }
+}
+
+/// Edits renaming to `to` the `DEF` name at the byte `offset`, or the one the `USE` at `offset`
+/// refers to, along with the `USE`s referring to it: the ones following it in its scope, up to the
+/// next definition of the same name. Fails if `to` is already defined in the scope.
+pub fn rename_def_at(code: &str, offset: usize, to: &str) -> Result<Vec<Edit>> {
+    if !is_identifier(to) {
+        return Err(anyhow!("\"{to}\" is not a valid DEF name."));
+    }
+    let tree = parse_valid(code)?;
+    let contains = |node: Node| node.start_byte() <= offset && offset <= node.end_byte();
+    let scopes = scopes(tree.root_node(), code);
+    let (scope, binding) = scopes
+        .iter()
+        .find_map(|scope| {
+            let binding = scope
+                .defs
+                .iter()
+                .chain(&scope.uses)
+                .find(|binding| contains(binding.identifier))?;
+            Some((scope, *binding))
+        })
+        .ok_or_else(|| anyhow!("No DEF or USE name at this position."))?;
+
+    let start = binding.identifier.start_byte();
+    let def = scope
+        .defs
+        .iter()
+        .rev()
+        .find(|def| def.name == binding.name && def.identifier.start_byte() <= start)
+        .ok_or_else(|| anyhow!("USE {} has no DEF to rename.", binding.name))?;
+    if def.name == to {
+        return Ok(Vec::new());
+    }
+    if let Some(existing) = scope.defs.iter().find(|other| other.name == to) {
+        let line = existing.identifier.start_position().row + 1;
+        return Err(anyhow!("DEF {to} already exists at line {line}."));
+    }
+
+    let def_start = def.identifier.start_byte();
+    let next_def = scope
+        .defs
+        .iter()
+        .filter(|other| other.name == def.name)
+        .map(|other| other.identifier.start_byte())
+        .find(|&other| other > def_start)
+        .unwrap_or(usize::MAX);
+    let mut edits = vec![replace(def.identifier, to)];
+    for usage in &scope.uses {
+        let start = usage.identifier.start_byte();
+        if usage.name == def.name && def_start < start && start < next_def {
+            edits.push(replace(usage.identifier, to));
+        }
+    }
+    Ok(edits)
+}
+
+/// The PROTO and world files under `dir`, in a stable order. Hidden directories are skipped.
+pub fn project_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+
+    let mut files = Vec::new();
+    for path in entries {
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if path.is_dir() && !hidden {
+            files.extend(project_files(&path)?);
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "proto" || extension == "wbt")
+        {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn replace(node: Node, text: &str) -> Edit {
+    Edit {
+        start: node.start_byte(),
+        end: node.end_byte(),
+        replacement: text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit::apply_edits;
+    use crate::testing::TempDir;
+
+    const ROBOT: &str = r#"#VRML_SIM R2023b utf8
+PROTO Robot [
+  field SFVec3f size 1 1 1
+  field SFString name "robot"
+]
+{
+  Solid {
+    name IS name
+    children [
+      Shape {
+        geometry Box {
+          size IS size
+        }
+      }
+    ]
+    %< const size = fields.size.value; const sizes = fields.sizes; >%
+  }
+}
+"#;
+
+    #[test]
+    fn fields_are_renamed_in_the_interface_bindings_and_templates() {
+        let (proto, edits) = rename_field(ROBOT, "size", "dimensions").unwrap();
+        assert_eq!(proto, "Robot");
+        assert_eq!(edits.len(), 3);
+        let renamed = apply_edits(ROBOT, &edits);
+        assert!(renamed.contains("  field SFVec3f dimensions 1 1 1\n"));
+        assert!(renamed.contains("          size IS dimensions\n"));
+        assert!(
+            renamed.contains("const size = fields.dimensions.value; const sizes = fields.sizes;")
+        );
+    }
+
+    #[test]
+    fn invalid_field_renames_are_refused() {
+        let error = |from, to| rename_field(ROBOT, from, to).unwrap_err().to_string();
+        assert_eq!(
+            error("size", "name"),
+            "PROTO Robot already has a field name."
+        );
+        assert_eq!(error("color", "colour"), "PROTO Robot has no field color.");
+        assert_eq!(error("size", "2d"), "\"2d\" is not a valid field name.");
+        assert_eq!(
+            rename_field("#VRML_SIM R2023b utf8\nSolid {\n}\n", "a", "b")
+                .unwrap_err()
+                .to_string(),
+            "No PROTO to rename a field of."
+        );
+    }
+
+    #[test]
+    fn instances_setting_the_field_are_renamed() {
+        let world = r#"#VRML_SIM R2023b utf8
+Robot {
+  size 1 2 3
+  name "a"
+}
+Solid {
+  size 1
+  children [
+    DEF R Robot {
+      size 2 2 2
+    }
+  ]
+}
+"#;
+        let edits = rename_instance_field(world, "Robot", "size", "dimensions").unwrap();
+        assert_eq!(edits.len(), 2);
+        let renamed = apply_edits(world, &edits);
+        assert_eq!(renamed.matches("dimensions").count(), 2);
+        assert!(renamed.contains("Solid {\n  size 1\n"));
+    }
+
+    #[test]
+    fn project_files_are_protos_and_worlds() {
+        let dir = TempDir::new("project");
+        for file in [
+            "worlds/b.wbt",
+            "worlds/a.wbt",
+            "protos/Robot.proto",
+            "protos/notes.txt",
+            ".git/old.proto",
+        ] {
+            dir.write(file, "");
+        }
+        let files = project_files(&dir).unwrap();
+        assert_eq!(
+            files,
+            [
+                dir.join("protos/Robot.proto"),
+                dir.join("worlds/a.wbt"),
+                dir.join("worlds/b.wbt"),
+            ]
+        );
+    }
+
+    #[test]
+    fn identifiers() {
+        assert!(is_identifier("_size2"));
+        assert!(!is_identifier(""));
+        assert!(!is_identifier("2d"));
+        assert!(!is_identifier("a-b"));
+    }
+
+    const SCOPES: &str = r#"#VRML_SIM R2023b utf8
+PROTO Wall [
+  field SFNode shape DEF SHAPE Shape { }
+  field MFNode parts [ DEF SHAPE Shape { } USE SHAPE ]
+]
+{
+  Solid {
+    children [
+      DEF SHAPE Shape { }
+      DEF BOX Solid { }
+    ]
+    boundingObject USE SHAPE
+  }
+}
+"#;
+
+    #[test]
+    fn defs_are_renamed_with_the_uses_of_their_scope() {
+        let edits = rename_def(SCOPES, "SHAPE", "BODY", Some(9)).unwrap();
+        let renamed = apply_edits(SCOPES, &edits);
+        assert!(renamed.contains("      DEF BODY Shape { }\n"));
+        assert!(renamed.contains("    boundingObject USE BODY\n"));
+        assert_eq!(renamed.matches("SHAPE").count(), 3);
+
+        let edits = rename_def(SCOPES, "SHAPE", "PART", Some(4)).unwrap();
+        let renamed = apply_edits(SCOPES, &edits);
+        assert!(renamed.contains("[ DEF PART Shape { } USE PART ]"));
+        assert_eq!(renamed.matches("SHAPE").count(), 3);
+    }
+
+    #[test]
+    fn ambiguous_and_conflicting_defs_are_refused() {
+        let error = |line, to| {
+            rename_def(SCOPES, "SHAPE", to, line)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error(None, "BODY"),
+            "DEF SHAPE is defined at lines 3, 4, 9, choose one with its line."
+        );
+        assert_eq!(error(Some(5), "BODY"), "No DEF SHAPE at line 5.");
+        assert_eq!(error(Some(9), "BOX"), "DEF BOX already exists at line 10.");
+        assert_eq!(error(Some(9), "a b"), "\"a b\" is not a valid DEF name.");
+        assert_eq!(
+            rename_def(SCOPES, "WHEEL", "TIRE", None)
+                .unwrap_err()
+                .to_string(),
+            "No DEF WHEEL."
+        );
+    }
+
+    #[test]
+    fn defs_are_renamed_by_position() {
+        let world = r#"#VRML_SIM R2023b utf8
+DEF A Solid { }
+Pose {
+  children [ USE A ]
+}
+DEF A Solid { }
+USE A
+"#;
+        let offset = world.find("USE A").unwrap() + "USE ".len();
+        let renamed = apply_edits(world, &rename_def_at(world, offset, "B").unwrap());
+        assert_eq!(
+            renamed,
+            world
+                .replacen("DEF A", "DEF B", 1)
+                .replacen("USE A", "USE B", 1)
+        );
+        assert_eq!(rename_def_at(world, offset, "A").unwrap(), []);
+        assert_eq!(
+            rename_def_at(world, 0, "B").unwrap_err().to_string(),
+            "No DEF or USE name at this position."
+        );
+    }
+}
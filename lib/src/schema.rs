@@ -0,0 +1,1229 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use super::edit::Edit;
+use super::header::Release;
+use super::lint::{Context, Report, Rule, Severity};
+use super::syntax::parent_node_type;
+
+/// Every node type Webots provides without an EXTERNPROTO.
+pub const BUILTIN_NODES: &[&str] = &[
+    "Accelerometer",
+    "Altimeter",
+    "Appearance",
+    "Background",
+    "BallJoint",
+    "BallJointParameters",
+    "Billboard",
+    "Box",
+    "Brake",
+    "CadShape",
+    "Camera",
+    "Capsule",
+    "Charger",
+    "Color",
+    "Compass",
+    "Cone",
+    "Connector",
+    "ContactProperties",
+    "Coordinate",
+    "Cylinder",
+    "Damping",
+    "DirectionalLight",
+    "Display",
+    "DistanceSensor",
+    "ElevationGrid",
+    "Emitter",
+    "Fluid",
+    "Focus",
+    "Fog",
+    "GPS",
+    "Group",
+    "Gyro",
+    "Hinge2Joint",
+    "HingeJoint",
+    "HingeJointParameters",
+    "ImageTexture",
+    "ImmersionProperties",
+    "IndexedFaceSet",
+    "IndexedLineSet",
+    "InertialUnit",
+    "JointParameters",
+    "LED",
+    "Lens",
+    "LensFlare",
+    "Lidar",
+    "LinearMotor",
+    "Material",
+    "Mesh",
+    "Muscle",
+    "Normal",
+    "PBRAppearance",
+    "Pen",
+    "Physics",
+    "Plane",
+    "PointLight",
+    "PointSet",
+    "Pose",
+    "PositionSensor",
+    "Propeller",
+    "Radar",
+    "RangeFinder",
+    "Receiver",
+    "Recognition",
+    "Robot",
+    "RotationalMotor",
+    "Shape",
+    "Skin",
+    "SliderJoint",
+    "Slot",
+    "Solid",
+    "SolidReference",
+    "Speaker",
+    "Sphere",
+    "SpotLight",
+    "TextureCoordinate",
+    "TextureTransform",
+    "TouchSensor",
+    "Track",
+    "TrackWheel",
+    "Transform",
+    "VacuumGripper",
+    "Viewpoint",
+    "WorldInfo",
+    "Zoom",
+];
+
+/// A field as listed in the tables: name, type and default value as written in a file. The
+/// default is left empty when it depends on the context.
+type Field = (&'static str, &'static str, &'static str);
+
+/// A built-in Webots node and its fields, as of the latest release. Fields of `base` are
+/// inherited, and listed again when the node changes their default.
+#[derive(Debug)]
+pub struct NodeSchema {
+    pub name: &'static str,
+    pub base: Option<&'static str>,
+    pub fields: &'static [Field],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldSchema {
+    pub name: &'static str,
+    pub field_type: &'static str,
+    pub default: Option<&'static str>,
+}
+
+/// How a node or field differed before a release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// It did not exist.
+    Added,
+    /// The field had another name.
+    Renamed { from: &'static str },
+    /// The field had another default value.
+    Default { before: &'static str },
+}
+
+/// A change of the built-in nodes. A `field` of `None` stands for the node itself.
+#[derive(Debug, Clone, Copy)]
+pub struct Revision {
+    pub release: Release,
+    pub node: &'static str,
+    pub field: Option<&'static str>,
+    pub change: Change,
+}
+
+const fn revision(
+    year: u16,
+    revision: char,
+    node: &'static str,
+    field: Option<&'static str>,
+    change: Change,
+) -> Revision {
+    Revision {
+        release: Release { year, revision },
+        node,
+        field,
+        change,
+    }
+}
+
+/// The changes to the built-in nodes, oldest first.
+pub static HISTORY: &[Revision] = &[
+    revision(
+        2022,
+        'a',
+        "Solid",
+        Some("rotation"),
+        Change::Default { before: "0 1 0 0" },
+    ),
+    revision(
+        2022,
+        'a',
+        "Pose",
+        Some("rotation"),
+        Change::Default { before: "0 1 0 0" },
+    ),
+    revision(
+        2022,
+        'a',
+        "WorldInfo",
+        Some("coordinateSystem"),
+        Change::Default { before: "\"NUE\"" },
+    ),
+    revision(
+        2022,
+        'b',
+        "Robot",
+        Some("customData"),
+        Change::Renamed { from: "data" },
+    ),
+    revision(2023, 'b', "Pose", None, Change::Added),
+];
+
+pub fn node_schema(name: &str) -> Option<&'static NodeSchema> {
+    SCHEMA.iter().find(|node| node.name == name)
+}
+
+/// The fields of the built-in node `node` in `release`, or the latest release if there is none,
+/// base fields first. Returns `None` for nodes without a schema and nodes that did not exist yet.
+pub fn node_fields(node: &str, release: Option<Release>) -> Option<Vec<FieldSchema>> {
+    let revisions = || {
+        HISTORY
+            .iter()
+            .rev()
+            .filter(move |r| release.is_some_and(|release| release < r.release))
+    };
+    let schema = node_schema(node)?;
+    if revisions().any(|r| r.node == node && r.field.is_none()) {
+        return None;
+    }
+
+    let mut layers = vec![schema];
+    while let Some(base) = layers.last().unwrap().base.and_then(node_schema) {
+        layers.push(base);
+    }
+
+    let mut fields: Vec<FieldSchema> = Vec::new();
+    for layer in layers.into_iter().rev() {
+        for &(name, field_type, default) in layer.fields {
+            let mut field = FieldSchema {
+                name,
+                field_type,
+                default: (!default.is_empty()).then_some(default),
+            };
+            let mut added = false;
+            for r in revisions().filter(|r| r.node == layer.name && r.field == Some(name)) {
+                match r.change {
+                    Change::Added => added = true,
+                    Change::Renamed { from } => field.name = from,
+                    Change::Default { before } => field.default = Some(before),
+                }
+            }
+            if added {
+                continue;
+            }
+            match fields.iter_mut().find(|f| f.name == field.name) {
+                Some(inherited) => *inherited = field,
+                None => fields.push(field),
+            }
+        }
+    }
+    Some(fields)
+}
+
+/// The field `field` of the built-in node `node` in `release`, looking into its base nodes.
+pub fn field_schema(node: &str, field: &str, release: Option<Release>) -> Option<FieldSchema> {
+    node_fields(node, release)?
+        .into_iter()
+        .find(|f| f.name == field)
+}
+
+/// The type of `field` in the built-in node `node`, looking into its base nodes.
+pub fn field_type(node: &str, field: &str, release: Option<Release>) -> Option<&'static str> {
+    field_schema(node, field, release).map(|field| field.field_type)
+}
+
+/// The last release changing the default value of `field` in the built-in node `node`, looking
+/// into its base nodes.
+pub fn default_change(node: &str, field: &str) -> Option<Release> {
+    let mut layer = node_schema(node);
+    let mut changes: Vec<&Revision> = Vec::new();
+    while let Some(schema) = layer {
+        changes.extend(HISTORY.iter().filter(|r| {
+            r.node == schema.name
+                && r.field == Some(field)
+                && matches!(r.change, Change::Default { .. })
+        }));
+        layer = schema.base.and_then(node_schema);
+    }
+    changes.into_iter().map(|r| r.release).max()
+}
+
+/// Fields of built-in nodes that the node does not have in the release of the file.
+pub struct UnknownField;
+
+impl Rule for UnknownField {
+    fn id(&self) -> &'static str {
+        "unknown-field"
+    }
+
+    fn description(&self) -> &'static str {
+        "field that the built-in node does not have"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, context: &Context, report: &mut Report) {
+        let mut stack = vec![context.root];
+        while let Some(node) = stack.pop() {
+            let mut cursor = node.walk();
+            stack.extend(node.children(&mut cursor));
+            if node.kind() != "property" {
+                continue;
+            }
+            let Some(node_type) = parent_node_type(node) else {
+                continue;
+            };
+            let node_type = context.text(node_type);
+            let Some(fields) = node_fields(node_type, context.release) else {
+                continue;
+            };
+            let Some(name) = node.child(0).filter(|n| n.kind() != "comment") else {
+                continue;
+            };
+            let field = context.text(name);
+            if fields.iter().any(|f| f.name == field) {
+                continue;
+            }
+
+            let renamed = HISTORY.iter().find(|r| {
+                matches!(r.change, Change::Renamed { from } if from == field)
+                    && context.release.is_none_or(|release| release >= r.release)
+                    && fields.iter().any(|f| Some(f.name) == r.field)
+            });
+            if let Some(renamed) = renamed {
+                let new_name = renamed.field.unwrap_or_default();
+                let message = format!(
+                    "{node_type}.{field} was renamed {new_name} in {}",
+                    renamed.release
+                );
+                report.add(name.range(), message).fix.push(Edit {
+                    start: name.start_byte(),
+                    end: name.end_byte(),
+                    replacement: new_name.to_string(),
+                });
+                continue;
+            }
+
+            let message = match closest(field, fields.iter().map(|f| f.name)) {
+                Some(suggestion) => {
+                    format!("{node_type} has no field {field}, did you mean {suggestion}?")
+                }
+                None => format!("{node_type} has no field {field}"),
+            };
+            report.add(name.range(), message);
+        }
+    }
+}
+
+/// The candidate closest to `name`, if it is only a typo away.
+fn closest<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2.max(name.len() / 4))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between `a` and `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+const SOLID: &[Field] = &[
+    ("translation", "SFVec3f", "0 0 0"),
+    ("rotation", "SFRotation", "0 0 1 0"),
+    ("children", "MFNode", "[]"),
+    ("name", "SFString", "\"solid\""),
+    ("model", "SFString", "\"\""),
+    ("description", "SFString", "\"\""),
+    ("contactMaterial", "SFString", "\"default\""),
+    ("immersionProperties", "MFNode", "[]"),
+    ("boundingObject", "SFNode", "NULL"),
+    ("physics", "SFNode", "NULL"),
+    ("locked", "SFBool", "FALSE"),
+    ("radarCrossSection", "SFFloat", "0"),
+    ("recognitionColors", "MFColor", "[]"),
+    ("translationStep", "SFFloat", "0.01"),
+    ("rotationStep", "SFFloat", "0.261799387"),
+    ("linearVelocity", "SFVec3f", "0 0 0"),
+    ("angularVelocity", "SFVec3f", "0 0 0"),
+];
+
+const MOTOR: &[Field] = &[
+    ("name", "SFString", ""),
+    ("acceleration", "SFFloat", "-1"),
+    ("consumptionFactor", "SFFloat", "10"),
+    ("controlPID", "SFVec3f", "10 0 0"),
+    ("minPosition", "SFFloat", "0"),
+    ("maxPosition", "SFFloat", "0"),
+    ("maxVelocity", "SFFloat", "10"),
+    ("multiplier", "SFFloat", "1"),
+    ("sound", "SFString", ""),
+    ("muscles", "MFNode", "[]"),
+];
+
+const JOINT_PARAMETERS: &[Field] = &[
+    ("position", "SFFloat", "0"),
+    ("axis", "SFVec3f", "0 0 1"),
+    ("minStop", "SFFloat", "0"),
+    ("maxStop", "SFFloat", "0"),
+    ("springConstant", "SFFloat", "0"),
+    ("dampingConstant", "SFFloat", "0"),
+    ("staticFriction", "SFFloat", "0"),
+];
+
+const LIGHT: &[Field] = &[
+    ("ambientIntensity", "SFFloat", "0"),
+    ("color", "SFColor", "1 1 1"),
+    ("intensity", "SFFloat", "1"),
+    ("on", "SFBool", "TRUE"),
+    ("castShadows", "SFBool", "FALSE"),
+];
+
+const POSITIONAL_LIGHT: &[Field] = &[
+    ("attenuation", "SFVec3f", "1 0 0"),
+    ("location", "SFVec3f", "0 0 0"),
+    ("radius", "SFFloat", "100"),
+];
+
+const fn node(
+    name: &'static str,
+    base: Option<&'static str>,
+    fields: &'static [Field],
+) -> NodeSchema {
+    NodeSchema { name, base, fields }
+}
+
+static SCHEMA: &[NodeSchema] = &[
+    node("Group", None, &[("children", "MFNode", "[]")]),
+    node("Billboard", Some("Group"), &[]),
+    node(
+        "Pose",
+        Some("Group"),
+        &[
+            ("translation", "SFVec3f", "0 0 0"),
+            ("rotation", "SFRotation", "0 0 1 0"),
+            ("translationStep", "SFFloat", "0.01"),
+            ("rotationStep", "SFFloat", "0.261799387"),
+        ],
+    ),
+    node("Transform", Some("Pose"), &[("scale", "SFVec3f", "1 1 1")]),
+    node("Solid", None, SOLID),
+    node(
+        "Robot",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"robot\""),
+            ("controller", "SFString", "\"<generic>\""),
+            ("controllerArgs", "MFString", "[]"),
+            ("customData", "SFString", "\"\""),
+            ("supervisor", "SFBool", "FALSE"),
+            ("synchronization", "SFBool", "TRUE"),
+            ("battery", "MFFloat", "[]"),
+            ("cpuConsumption", "SFFloat", "10"),
+            ("selfCollision", "SFBool", "FALSE"),
+            ("window", "SFString", "\"<generic>\""),
+            ("remoteControl", "SFString", "\"<none>\""),
+        ],
+    ),
+    node(
+        "Shape",
+        None,
+        &[
+            ("appearance", "SFNode", "NULL"),
+            ("geometry", "SFNode", "NULL"),
+            ("castShadows", "SFBool", "TRUE"),
+            ("isPickable", "SFBool", "TRUE"),
+        ],
+    ),
+    node(
+        "CadShape",
+        None,
+        &[
+            ("url", "MFString", "[]"),
+            ("ccw", "SFBool", "TRUE"),
+            ("castShadows", "SFBool", "TRUE"),
+            ("isPickable", "SFBool", "TRUE"),
+        ],
+    ),
+    node(
+        "Appearance",
+        None,
+        &[
+            ("material", "SFNode", "NULL"),
+            ("texture", "SFNode", "NULL"),
+            ("textureTransform", "SFNode", "NULL"),
+            ("name", "SFString", "\"appearance\""),
+        ],
+    ),
+    node(
+        "PBRAppearance",
+        None,
+        &[
+            ("baseColor", "SFColor", "1 1 1"),
+            ("baseColorMap", "SFNode", "NULL"),
+            ("transparency", "SFFloat", "0"),
+            ("roughness", "SFFloat", "0"),
+            ("roughnessMap", "SFNode", "NULL"),
+            ("metalness", "SFFloat", "1"),
+            ("metalnessMap", "SFNode", "NULL"),
+            ("IBLStrength", "SFFloat", "1"),
+            ("normalMap", "SFNode", "NULL"),
+            ("normalMapFactor", "SFFloat", "1"),
+            ("occlusionMap", "SFNode", "NULL"),
+            ("occlusionMapStrength", "SFFloat", "1"),
+            ("emissiveColor", "SFColor", "0 0 0"),
+            ("emissiveColorMap", "SFNode", "NULL"),
+            ("emissiveIntensity", "SFFloat", "1"),
+            ("textureTransform", "SFNode", "NULL"),
+            ("name", "SFString", "\"PBRAppearance\""),
+        ],
+    ),
+    node(
+        "Material",
+        None,
+        &[
+            ("ambientIntensity", "SFFloat", "0.2"),
+            ("diffuseColor", "SFColor", "0.8 0.8 0.8"),
+            ("emissiveColor", "SFColor", "0 0 0"),
+            ("shininess", "SFFloat", "0.2"),
+            ("specularColor", "SFColor", "0 0 0"),
+            ("transparency", "SFFloat", "0"),
+        ],
+    ),
+    node(
+        "ImageTexture",
+        None,
+        &[
+            ("url", "MFString", "[]"),
+            ("repeatS", "SFBool", "TRUE"),
+            ("repeatT", "SFBool", "TRUE"),
+            ("filtering", "SFInt32", "4"),
+        ],
+    ),
+    node(
+        "TextureTransform",
+        None,
+        &[
+            ("center", "SFVec2f", "0 0"),
+            ("rotation", "SFFloat", "0"),
+            ("scale", "SFVec2f", "1 1"),
+            ("translation", "SFVec2f", "0 0"),
+        ],
+    ),
+    node("Box", None, &[("size", "SFVec3f", "0.1 0.1 0.1")]),
+    node(
+        "Sphere",
+        None,
+        &[
+            ("radius", "SFFloat", "0.1"),
+            ("subdivision", "SFInt32", "1"),
+            ("ico", "SFBool", "TRUE"),
+        ],
+    ),
+    node(
+        "Cylinder",
+        None,
+        &[
+            ("bottom", "SFBool", "TRUE"),
+            ("height", "SFFloat", "0.1"),
+            ("radius", "SFFloat", "0.05"),
+            ("side", "SFBool", "TRUE"),
+            ("top", "SFBool", "TRUE"),
+            ("subdivision", "SFInt32", "36"),
+        ],
+    ),
+    node(
+        "Capsule",
+        None,
+        &[
+            ("bottom", "SFBool", "TRUE"),
+            ("height", "SFFloat", "0.1"),
+            ("radius", "SFFloat", "0.05"),
+            ("side", "SFBool", "TRUE"),
+            ("top", "SFBool", "TRUE"),
+            ("subdivision", "SFInt32", "12"),
+        ],
+    ),
+    node(
+        "Cone",
+        None,
+        &[
+            ("bottomRadius", "SFFloat", "0.05"),
+            ("height", "SFFloat", "0.1"),
+            ("side", "SFBool", "TRUE"),
+            ("bottom", "SFBool", "TRUE"),
+            ("subdivision", "SFInt32", "12"),
+        ],
+    ),
+    node("Plane", None, &[("size", "SFVec2f", "1 1")]),
+    node(
+        "ElevationGrid",
+        None,
+        &[
+            ("color", "SFNode", "NULL"),
+            ("texCoord", "SFNode", "NULL"),
+            ("height", "MFFloat", "[]"),
+            ("colorPerVertex", "SFBool", "TRUE"),
+            ("xDimension", "SFInt32", "0"),
+            ("xSpacing", "SFFloat", "1"),
+            ("yDimension", "SFInt32", "0"),
+            ("ySpacing", "SFFloat", "1"),
+            ("thickness", "SFFloat", "1"),
+        ],
+    ),
+    node(
+        "IndexedFaceSet",
+        None,
+        &[
+            ("coord", "SFNode", "NULL"),
+            ("normal", "SFNode", "NULL"),
+            ("texCoord", "SFNode", "NULL"),
+            ("solid", "SFBool", "TRUE"),
+            ("ccw", "SFBool", "TRUE"),
+            ("convex", "SFBool", "TRUE"),
+            ("normalPerVertex", "SFBool", "TRUE"),
+            ("coordIndex", "MFInt32", "[]"),
+            ("normalIndex", "MFInt32", "[]"),
+            ("texCoordIndex", "MFInt32", "[]"),
+            ("creaseAngle", "SFFloat", "0"),
+        ],
+    ),
+    node(
+        "IndexedLineSet",
+        None,
+        &[("coord", "SFNode", "NULL"), ("coordIndex", "MFInt32", "[]")],
+    ),
+    node("Coordinate", None, &[("point", "MFVec3f", "[]")]),
+    node("Normal", None, &[("vector", "MFVec3f", "[]")]),
+    node("TextureCoordinate", None, &[("point", "MFVec2f", "[]")]),
+    node("Color", None, &[("color", "MFColor", "[]")]),
+    node(
+        "Mesh",
+        None,
+        &[
+            ("url", "MFString", "[]"),
+            ("ccw", "SFBool", "TRUE"),
+            ("name", "SFString", "\"\""),
+            ("materialIndex", "SFInt32", "-1"),
+        ],
+    ),
+    node(
+        "Physics",
+        None,
+        &[
+            ("density", "SFFloat", "1000"),
+            ("mass", "SFFloat", "-1"),
+            ("centerOfMass", "MFVec3f", "[]"),
+            ("inertiaMatrix", "MFVec3f", "[]"),
+            ("damping", "SFNode", "NULL"),
+        ],
+    ),
+    node(
+        "Damping",
+        None,
+        &[("linear", "SFFloat", "0.2"), ("angular", "SFFloat", "0.2")],
+    ),
+    node(
+        "HingeJoint",
+        None,
+        &[
+            ("jointParameters", "SFNode", "NULL"),
+            ("device", "MFNode", "[]"),
+            ("endPoint", "SFNode", "NULL"),
+        ],
+    ),
+    node("SliderJoint", Some("HingeJoint"), &[]),
+    node(
+        "Hinge2Joint",
+        Some("HingeJoint"),
+        &[
+            ("jointParameters2", "SFNode", "NULL"),
+            ("device2", "MFNode", "[]"),
+        ],
+    ),
+    node(
+        "BallJoint",
+        Some("Hinge2Joint"),
+        &[
+            ("jointParameters3", "SFNode", "NULL"),
+            ("device3", "MFNode", "[]"),
+        ],
+    ),
+    node("JointParameters", None, JOINT_PARAMETERS),
+    node(
+        "HingeJointParameters",
+        Some("JointParameters"),
+        &[
+            ("axis", "SFVec3f", "1 0 0"),
+            ("anchor", "SFVec3f", "0 0 0"),
+            ("suspensionSpringConstant", "SFFloat", "0"),
+            ("suspensionDampingConstant", "SFFloat", "0"),
+            ("suspensionAxis", "SFVec3f", "1 0 0"),
+            ("stopERP", "SFFloat", "-1"),
+            ("stopCFM", "SFFloat", "-1"),
+        ],
+    ),
+    node(
+        "BallJointParameters",
+        None,
+        &[
+            ("position", "SFFloat", "0"),
+            ("anchor", "SFVec3f", "0 0 0"),
+            ("minStop", "SFFloat", "0"),
+            ("maxStop", "SFFloat", "0"),
+            ("springConstant", "SFFloat", "0"),
+            ("dampingConstant", "SFFloat", "0"),
+        ],
+    ),
+    node("Motor", None, MOTOR),
+    node(
+        "RotationalMotor",
+        Some("Motor"),
+        &[
+            ("name", "SFString", "\"rotational motor\""),
+            (
+                "sound",
+                "SFString",
+                "\"webots://projects/default/worlds/sounds/rotational_motor.wav\"",
+            ),
+            ("maxTorque", "SFFloat", "10"),
+        ],
+    ),
+    node(
+        "LinearMotor",
+        Some("Motor"),
+        &[
+            ("name", "SFString", "\"linear motor\""),
+            (
+                "sound",
+                "SFString",
+                "\"webots://projects/default/worlds/sounds/linear_motor.wav\"",
+            ),
+            ("maxForce", "SFFloat", "10"),
+        ],
+    ),
+    node(
+        "PositionSensor",
+        None,
+        &[
+            ("name", "SFString", "\"position sensor\""),
+            ("noise", "SFFloat", "0"),
+            ("resolution", "SFFloat", "-1"),
+        ],
+    ),
+    node("Brake", None, &[("name", "SFString", "\"brake\"")]),
+    node(
+        "Camera",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"camera\""),
+            ("fieldOfView", "SFFloat", "0.7854"),
+            ("width", "SFInt32", "64"),
+            ("height", "SFInt32", "64"),
+            ("projection", "SFString", "\"planar\""),
+            ("near", "SFFloat", "0.01"),
+            ("far", "SFFloat", "0"),
+            ("exposure", "SFFloat", "1"),
+            ("antiAliasing", "SFBool", "FALSE"),
+            ("ambientOcclusionRadius", "SFFloat", "0"),
+            ("bloomThreshold", "SFFloat", "-1"),
+            ("motionBlur", "SFFloat", "0"),
+            ("noise", "SFFloat", "0"),
+            ("noiseMaskUrl", "SFString", "\"\""),
+            ("lens", "SFNode", "NULL"),
+            ("focus", "SFNode", "NULL"),
+            ("zoom", "SFNode", "NULL"),
+            ("recognition", "SFNode", "NULL"),
+            ("lensFlare", "SFNode", "NULL"),
+        ],
+    ),
+    node(
+        "RangeFinder",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"range-finder\""),
+            ("fieldOfView", "SFFloat", "0.7854"),
+            ("width", "SFInt32", "64"),
+            ("height", "SFInt32", "64"),
+            ("projection", "SFString", "\"planar\""),
+            ("near", "SFFloat", "0.01"),
+            ("minRange", "SFFloat", "0.01"),
+            ("maxRange", "SFFloat", "1"),
+            ("motionBlur", "SFFloat", "0"),
+            ("noise", "SFFloat", "0"),
+            ("resolution", "SFFloat", "-1"),
+            ("lens", "SFNode", "NULL"),
+        ],
+    ),
+    node(
+        "Lidar",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"lidar\""),
+            ("tiltAngle", "SFFloat", "0"),
+            ("horizontalResolution", "SFInt32", "512"),
+            ("fieldOfView", "SFFloat", "1.5708"),
+            ("verticalFieldOfView", "SFFloat", "0.2"),
+            ("numberOfLayers", "SFInt32", "4"),
+            ("near", "SFFloat", "0.01"),
+            ("minRange", "SFFloat", "0.01"),
+            ("maxRange", "SFFloat", "1"),
+            ("type", "SFString", "\"fixed\""),
+            ("projection", "SFString", "\"cylindrical\""),
+            ("noise", "SFFloat", "0"),
+            ("resolution", "SFFloat", "-1"),
+            ("defaultFrequency", "SFFloat", "10"),
+            ("minFrequency", "SFFloat", "1"),
+            ("maxFrequency", "SFFloat", "25"),
+            ("rotatingHead", "SFNode", "NULL"),
+        ],
+    ),
+    node(
+        "DistanceSensor",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"distance sensor\""),
+            ("lookupTable", "MFVec3f", "[0 0 0, 0.1 1000 0]"),
+            ("type", "SFString", "\"generic\""),
+            ("numberOfRays", "SFInt32", "1"),
+            ("aperture", "SFFloat", "1.5708"),
+            ("gaussianWidth", "SFFloat", "1"),
+            ("resolution", "SFFloat", "-1"),
+            ("redColorSensitivity", "SFFloat", "1"),
+        ],
+    ),
+    node(
+        "GPS",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"gps\""),
+            ("type", "SFString", "\"satellite\""),
+            ("accuracy", "SFFloat", "0"),
+            ("noiseCorrelation", "SFFloat", "0"),
+            ("resolution", "SFFloat", "-1"),
+            ("speedNoise", "SFFloat", "0"),
+            ("speedResolution", "SFFloat", "-1"),
+        ],
+    ),
+    node(
+        "Gyro",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"gyro\""),
+            ("lookupTable", "MFVec3f", "[]"),
+            ("xAxis", "SFBool", "TRUE"),
+            ("yAxis", "SFBool", "TRUE"),
+            ("zAxis", "SFBool", "TRUE"),
+            ("resolution", "SFFloat", "-1"),
+        ],
+    ),
+    node(
+        "Accelerometer",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"accelerometer\""),
+            ("lookupTable", "MFVec3f", "[]"),
+            ("xAxis", "SFBool", "TRUE"),
+            ("yAxis", "SFBool", "TRUE"),
+            ("zAxis", "SFBool", "TRUE"),
+            ("resolution", "SFFloat", "-1"),
+        ],
+    ),
+    node(
+        "Compass",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"compass\""),
+            ("lookupTable", "MFVec3f", "[]"),
+            ("xAxis", "SFBool", "TRUE"),
+            ("yAxis", "SFBool", "TRUE"),
+            ("zAxis", "SFBool", "TRUE"),
+            ("resolution", "SFFloat", "-1"),
+        ],
+    ),
+    node(
+        "InertialUnit",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"inertial unit\""),
+            ("xAxis", "SFBool", "TRUE"),
+            ("yAxis", "SFBool", "TRUE"),
+            ("zAxis", "SFBool", "TRUE"),
+            ("resolution", "SFFloat", "-1"),
+            ("noise", "SFFloat", "0"),
+        ],
+    ),
+    node(
+        "TouchSensor",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"touch sensor\""),
+            ("type", "SFString", "\"bumper\""),
+            ("lookupTable", "MFVec3f", "[0 0 0, 5000 50000 0]"),
+            ("resolution", "SFFloat", "-1"),
+        ],
+    ),
+    node(
+        "LED",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"led\""),
+            ("color", "MFColor", "[1 0 0]"),
+            ("gradual", "SFBool", "FALSE"),
+        ],
+    ),
+    node(
+        "Emitter",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"emitter\""),
+            ("type", "SFString", "\"radio\""),
+            ("range", "SFFloat", "-1"),
+            ("maxRange", "SFFloat", "-1"),
+            ("aperture", "SFFloat", "-1"),
+            ("channel", "SFInt32", "0"),
+            ("baudRate", "SFInt32", "-1"),
+            ("byteSize", "SFInt32", "8"),
+            ("bufferSize", "SFInt32", "-1"),
+            ("allowedChannels", "MFInt32", "[]"),
+        ],
+    ),
+    node(
+        "Receiver",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"receiver\""),
+            ("type", "SFString", "\"radio\""),
+            ("aperture", "SFFloat", "-1"),
+            ("channel", "SFInt32", "0"),
+            ("baudRate", "SFInt32", "-1"),
+            ("byteSize", "SFInt32", "8"),
+            ("bufferSize", "SFInt32", "-1"),
+            ("signalStrengthNoise", "SFFloat", "0"),
+            ("directionNoise", "SFFloat", "0"),
+            ("allowedChannels", "MFInt32", "[]"),
+        ],
+    ),
+    node(
+        "Display",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"display\""),
+            ("width", "SFInt32", "64"),
+            ("height", "SFInt32", "64"),
+        ],
+    ),
+    node(
+        "Pen",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"pen\""),
+            ("inkColor", "SFColor", "0 0 0"),
+            ("inkDensity", "SFFloat", "0.5"),
+            ("leadSize", "SFFloat", "0.002"),
+            ("maxDistance", "SFFloat", "0"),
+            ("write", "SFBool", "TRUE"),
+        ],
+    ),
+    node(
+        "Speaker",
+        Some("Solid"),
+        &[("name", "SFString", "\"speaker\"")],
+    ),
+    node(
+        "Connector",
+        Some("Solid"),
+        &[
+            ("name", "SFString", "\"connector\""),
+            ("type", "SFString", "\"symmetric\""),
+            ("isLocked", "SFBool", "FALSE"),
+            ("autoLock", "SFBool", "FALSE"),
+            ("unilateralLock", "SFBool", "TRUE"),
+            ("unilateralUnlock", "SFBool", "TRUE"),
+            ("distanceTolerance", "SFFloat", "0.01"),
+            ("axisTolerance", "SFFloat", "0.2"),
+            ("rotationTolerance", "SFFloat", "0.2"),
+            ("numberOfRotations", "SFInt32", "4"),
+            ("snap", "SFBool", "TRUE"),
+            ("tensileStrength", "SFFloat", "-1"),
+            ("shearStrength", "SFFloat", "-1"),
+        ],
+    ),
+    node(
+        "Propeller",
+        None,
+        &[
+            ("shaftAxis", "SFVec3f", "1 0 0"),
+            ("centerOfThrust", "SFVec3f", "0 0 0"),
+            ("thrustConstants", "SFVec2f", "1 0"),
+            ("torqueConstants", "SFVec2f", "1 0"),
+            ("fastHelixThreshold", "SFFloat", "75.4"),
+            ("device", "SFNode", "NULL"),
+            ("fastHelix", "SFNode", "NULL"),
+            ("slowHelix", "SFNode", "NULL"),
+        ],
+    ),
+    node(
+        "Slot",
+        None,
+        &[("type", "SFString", "\"\""), ("endPoint", "SFNode", "NULL")],
+    ),
+    node(
+        "WorldInfo",
+        None,
+        &[
+            ("info", "MFString", "[]"),
+            ("title", "SFString", "\"\""),
+            ("window", "SFString", "\"<none>\""),
+            ("gravity", "SFFloat", "9.81"),
+            ("CFM", "SFFloat", "1e-05"),
+            ("ERP", "SFFloat", "0.2"),
+            ("physics", "SFString", "\"<none>\""),
+            ("basicTimeStep", "SFFloat", "32"),
+            ("FPS", "SFFloat", "60"),
+            ("optimalThreadCount", "SFInt32", "1"),
+            ("physicsDisableTime", "SFFloat", "1"),
+            ("physicsDisableLinearThreshold", "SFFloat", "0.01"),
+            ("physicsDisableAngularThreshold", "SFFloat", "0.01"),
+            ("defaultDamping", "SFNode", "NULL"),
+            ("inkEvaporation", "SFFloat", "0"),
+            ("coordinateSystem", "SFString", "\"ENU\""),
+            ("gpsCoordinateSystem", "SFString", "\"local\""),
+            ("gpsReference", "SFVec3f", "0 0 0"),
+            ("lineScale", "SFFloat", "0.1"),
+            ("dragForceScale", "SFFloat", "30"),
+            ("dragTorqueScale", "SFFloat", "5"),
+            ("randomSeed", "SFInt32", "0"),
+            ("contactProperties", "MFNode", "[]"),
+        ],
+    ),
+    node(
+        "Viewpoint",
+        None,
+        &[
+            ("fieldOfView", "SFFloat", "0.785398"),
+            ("orientation", "SFRotation", "0 0 1 0"),
+            ("position", "SFVec3f", ""),
+            ("description", "SFString", "\"\""),
+            ("near", "SFFloat", "0.05"),
+            ("far", "SFFloat", "0"),
+            ("exposure", "SFFloat", "1"),
+            ("follow", "SFString", "\"\""),
+            ("followType", "SFString", "\"Tracking Shot\""),
+            ("followSmoothness", "SFFloat", "0.5"),
+            ("lensFlare", "SFNode", "NULL"),
+            ("ambientOcclusionRadius", "SFFloat", ""),
+            ("bloomThreshold", "SFFloat", ""),
+        ],
+    ),
+    node(
+        "Background",
+        None,
+        &[
+            ("skyColor", "MFColor", "[0 0 0]"),
+            ("backUrl", "MFString", "[]"),
+            ("bottomUrl", "MFString", "[]"),
+            ("frontUrl", "MFString", "[]"),
+            ("leftUrl", "MFString", "[]"),
+            ("rightUrl", "MFString", "[]"),
+            ("topUrl", "MFString", "[]"),
+            ("backIrradianceUrl", "MFString", "[]"),
+            ("bottomIrradianceUrl", "MFString", "[]"),
+            ("frontIrradianceUrl", "MFString", "[]"),
+            ("leftIrradianceUrl", "MFString", "[]"),
+            ("rightIrradianceUrl", "MFString", "[]"),
+            ("topIrradianceUrl", "MFString", "[]"),
+            ("luminosity", "SFFloat", "1"),
+        ],
+    ),
+    node(
+        "DirectionalLight",
+        Some("Light"),
+        &[("direction", "SFVec3f", "0 0 -1")],
+    ),
+    node("PointLight", Some("Light"), POSITIONAL_LIGHT),
+    node(
+        "SpotLight",
+        Some("PointLight"),
+        &[
+            ("beamWidth", "SFFloat", "1.570796"),
+            ("cutOffAngle", "SFFloat", "0.785398"),
+            ("direction", "SFVec3f", "0 0 -1"),
+        ],
+    ),
+    node("Light", None, LIGHT),
+    node(
+        "Fog",
+        None,
+        &[
+            ("color", "SFColor", "1 1 1"),
+            ("fogType", "SFString", "\"LINEAR\""),
+            ("visibilityRange", "SFFloat", "0"),
+        ],
+    ),
+    node(
+        "ContactProperties",
+        None,
+        &[
+            ("material1", "SFString", "\"default\""),
+            ("material2", "SFString", "\"default\""),
+            ("coulombFriction", "MFFloat", "[1]"),
+            ("frictionRotation", "SFVec2f", "0 0"),
+            ("rollingFriction", "SFVec3f", "0 0 0"),
+            ("bounce", "SFFloat", "0.5"),
+            ("bounceVelocity", "SFFloat", "0.01"),
+            ("forceDependentSlip", "MFFloat", "[0]"),
+            ("softERP", "SFFloat", "0.2"),
+            ("softCFM", "SFFloat", "0.001"),
+            ("bumpSound", "SFString", ""),
+            ("rollSound", "SFString", ""),
+            ("slideSound", "SFString", ""),
+            ("maxContactJoints", "SFInt32", "10"),
+        ],
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lint::tests::{fix, messages};
+
+    fn release(text: &str) -> Option<Release> {
+        Some(Release::parse(text).unwrap())
+    }
+
+    #[test]
+    fn fields_are_inherited_base_first() {
+        let fields = node_fields("Robot", None).unwrap();
+        assert_eq!(fields[0].name, "translation");
+        let name = fields.iter().find(|f| f.name == "name").unwrap();
+        assert_eq!(name.default, Some("\"robot\""));
+        assert_eq!(fields.iter().filter(|f| f.name == "name").count(), 1);
+        assert_eq!(field_type("Robot", "locked", None), Some("SFBool"));
+        assert_eq!(field_type("Robot", "scale", None), None);
+        assert_eq!(node_fields("Wall", None), None);
+    }
+
+    #[test]
+    fn fields_follow_the_release() {
+        let rotation = |node, text| {
+            field_schema(node, "rotation", release(text))
+                .unwrap()
+                .default
+        };
+        assert_eq!(rotation("Solid", "R2021b"), Some("0 1 0 0"));
+        assert_eq!(rotation("Solid", "R2022a"), Some("0 0 1 0"));
+        assert_eq!(rotation("Transform", "R2021b"), Some("0 1 0 0"));
+
+        assert!(field_schema("Robot", "data", release("R2022a")).is_some());
+        assert!(field_schema("Robot", "customData", release("R2022a")).is_none());
+        assert!(field_schema("Robot", "customData", release("R2022b")).is_some());
+
+        assert_eq!(node_fields("Pose", release("R2023a")), None);
+        assert!(node_fields("Pose", release("R2023b")).is_some());
+        assert!(field_schema("Transform", "translation", release("R2023a")).is_some());
+    }
+
+    #[test]
+    fn default_changes_are_inherited() {
+        assert_eq!(default_change("Solid", "rotation"), release("R2022a"));
+        assert_eq!(default_change("Robot", "rotation"), release("R2022a"));
+        assert_eq!(default_change("Transform", "rotation"), release("R2022a"));
+        assert_eq!(default_change("Solid", "translation"), None);
+    }
+
+    #[test]
+    fn closest_names() {
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "abc"), 3);
+        let names = ["translation", "rotation", "name"];
+        assert_eq!(
+            closest("tranlation", names.into_iter()),
+            Some("translation")
+        );
+        assert_eq!(closest("nmae", names.into_iter()), Some("name"));
+        assert_eq!(closest("physics", names.into_iter()), None);
+    }
+
+    #[test]
+    fn unknown_fields_are_reported() {
+        let code = r#"#VRML_SIM R2023b utf8
+Robot {
+  tranlation 0 0 1
+  fooBarBaz 1
+  data "x"
+  children [
+    Shape {
+      castShadow FALSE
+    }
+  ]
+}
+Wall {
+  anything 1
+}
+"#;
+        assert_eq!(
+            messages("unknown-field", code),
+            [
+                (
+                    3,
+                    "Robot has no field tranlation, did you mean translation?".to_string()
+                ),
+                (4, "Robot has no field fooBarBaz".to_string()),
+                (5, "Robot.data was renamed customData in R2022b".to_string()),
+                (
+                    8,
+                    "Shape has no field castShadow, did you mean castShadows?".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            fix("unknown-field", code),
+            code.replace("data \"x\"", "customData \"x\"")
+        );
+    }
+
+    #[test]
+    fn renamed_fields_are_valid_in_older_releases() {
+        let code = r#"#VRML_SIM R2022a utf8
+Robot {
+  data "x"
+  customData "y"
+}
+"#;
+        assert_eq!(
+            messages("unknown-field", code),
+            [(4, "Robot has no field customData".to_string())]
+        );
+    }
+}
@@ -0,0 +1,176 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use anyhow::{anyhow, Context, Result};
+use tree_sitter::{Node, Tree};
+
+/// Parses PROTO or world code. The tree may contain errors, see [`find_first_error_node`].
+pub fn parse(code: &str) -> Result<Tree> {
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(&tree_sitter_wbproto::language())
+        .with_context(|| "Could not set Tree-Sitter language")?;
+
+    parser
+        .parse(code, None)
+        .ok_or_else(|| anyhow!("Could not parse file."))
+}
+
+pub fn find_first_error_node(node: Node) -> Option<Node> {
+    if node.is_error() || node.is_missing() {
+        return Some(node);
+    }
+    for child in node.children(&mut node.walk()) {
+        if let Some(error_node) = find_first_error_node(child) {
+            return Some(error_node);
+        }
+    }
+    None
+}
+
+/// Parses PROTO or world code, failing with the line of the first syntax error, if any.
+pub fn parse_valid(code: &str) -> Result<Tree> {
+    let tree = parse(code)?;
+    if let Some(error_node) = find_first_error_node(tree.root_node()) {
+        let line = error_node.start_position().row + 1;
+        return Err(anyhow!("Parsed file contain errors (at line {line})."));
+    }
+    Ok(tree)
+}
+
+/// The source text of `node`.
+pub fn text<'a>(node: Node, code: &'a str) -> &'a str {
+    node.utf8_text(code.as_bytes()).unwrap_or_default()
+}
+
+/// A PROTO interface declaration, like `field SFString{"low", "high"} quality "low"`.
+#[derive(Debug, Clone)]
+pub struct FieldDecl<'a> {
+    pub kind: Node<'a>,
+    pub field_type: Node<'a>,
+    pub restrictions: Vec<Node<'a>>,
+    pub name: Node<'a>,
+    pub value: Node<'a>,
+}
+
+impl<'a> FieldDecl<'a> {
+    pub fn new(node: Node<'a>) -> Result<FieldDecl<'a>> {
+        let mut cursor = node.walk();
+        let children: Vec<Node> = node.children(&mut cursor).collect();
+        let at = |i: usize, what: &str| {
+            children.get(i).copied().ok_or_else(|| {
+                anyhow!(
+                    "Could not extract field {} around line {}",
+                    what,
+                    node.start_position().row + 1
+                )
+            })
+        };
+
+        let kind = at(0, "kind")?;
+        let field_type = at(1, "type")?;
+        let name = at(children.len().max(4) - 2, "name")?;
+        let value = at(children.len().max(4) - 1, "value")?;
+
+        let mut restrictions = Vec::new();
+        for child in &children[2..children.len() - 2] {
+            collect_restrictions(*child, &mut restrictions);
+        }
+
+        Ok(FieldDecl {
+            kind,
+            field_type,
+            restrictions,
+            name,
+            value,
+        })
+    }
+}
+
+/// Restriction lists may come as loose tokens or wrapped in a node of their own, so braces and
+/// commas are dropped and wrappers flattened, leaving only the allowed values.
+fn collect_restrictions<'a>(node: Node<'a>, restrictions: &mut Vec<Node<'a>>) {
+    match node.kind() {
+        "{" | "}" | "," | "comment" => {}
+        "node" | "vector" | "javascript_expression" => restrictions.push(node),
+        _ if node.child(0).is_some_and(|c| c.kind() == "{") => {
+            let mut cursor = node.walk();
+            for child in node.children(&mut cursor) {
+                collect_restrictions(child, restrictions);
+            }
+        }
+        _ => restrictions.push(node),
+    }
+}
+
+/// The three shapes a `node` takes: `DEF name Type { ... }`, `USE name` and `Type { ... }`.
+#[derive(Debug, Clone, Copy)]
+pub enum NodeForm<'a> {
+    Def { name: Node<'a>, node_type: Node<'a> },
+    Use { name: Node<'a> },
+    Plain { node_type: Node<'a> },
+}
+
+impl<'a> NodeForm<'a> {
+    pub fn new(node: Node<'a>) -> Option<NodeForm<'a>> {
+        match node.child(0)?.kind() {
+            "DEF" => Some(NodeForm::Def {
+                name: node.named_child(0)?,
+                node_type: node.named_child(1)?,
+            }),
+            "USE" => Some(NodeForm::Use {
+                name: node.named_child(0)?,
+            }),
+            _ => Some(NodeForm::Plain {
+                node_type: node.named_child(0)?,
+            }),
+        }
+    }
+
+    /// The node type, unless this is a `USE`.
+    pub fn node_type(&self) -> Option<Node<'a>> {
+        match self {
+            NodeForm::Def { node_type, .. } | NodeForm::Plain { node_type } => Some(*node_type),
+            NodeForm::Use { .. } => None,
+        }
+    }
+}
+
+/// A field of a node, like `translation 0 0 1` or `translation IS position`.
+#[derive(Debug, Clone)]
+pub struct Property<'a> {
+    pub name: Node<'a>,
+    pub is_target: Option<Node<'a>>,
+    pub values: Vec<Node<'a>>,
+}
+
+impl<'a> Property<'a> {
+    pub fn new(node: Node<'a>) -> Option<Property<'a>> {
+        let mut cursor = node.walk();
+        let mut children = node.children(&mut cursor).filter(|c| c.kind() != "comment");
+        let name = children.next()?;
+        let mut is_target = None;
+        let mut values = Vec::new();
+        while let Some(child) = children.next() {
+            if child.kind() == "IS" {
+                is_target = children.next();
+            } else {
+                values.push(child);
+            }
+        }
+        Some(Property {
+            name,
+            is_target,
+            values,
+        })
+    }
+}
+
+/// The node type of the node `property` belongs to, if it is not a template.
+pub fn parent_node_type<'a>(property: Node<'a>) -> Option<Node<'a>> {
+    let parent = property.parent().filter(|p| p.kind() == "node")?;
+    NodeForm::new(parent)?.node_type()
+}
@@ -0,0 +1,579 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use rquickjs::loader::{FileResolver, ScriptLoader};
+use rquickjs::{CatchResultExt, Context, Function, Module, Runtime};
+use tree_sitter::Node;
+
+use super::config::Config;
+use super::header::Header;
+use super::interface::{interface, protos};
+use super::schema::node_fields;
+use super::syntax::{parse, parse_valid, text, FieldDecl, NodeForm, Property};
+use super::types::ValueShape;
+
+/// Directories of a Webots installation holding the modules templates import, like
+/// `wbgeometry.js`.
+const MODULE_DIRS: [&str; 2] = [
+    "resources/templating/modules",
+    "resources/web/wwi/protoVisualizer/templating/modules",
+];
+
+/// Parses `NAME=VALUE` arguments, the values being written as in a PROTO file.
+pub fn field_values(arguments: &[String]) -> Result<BTreeMap<String, String>> {
+    arguments
+        .iter()
+        .map(|text| {
+            let (name, value) = text
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid field \"{text}\", expected NAME=VALUE"))?;
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Evaluates the JavaScript template of the PROTO in `code` and returns the PROTO with its body
+/// replaced by the generated text. Fields take their value from `values`, given as PROTO
+/// source text, or else their default. `path` is the PROTO file, if any, which the template
+/// context refers to.
+pub fn expand(
+    code: &str,
+    path: Option<&Path>,
+    values: &BTreeMap<String, String>,
+    config: &Config,
+) -> Result<String> {
+    let tree = parse_valid(code)?;
+    let root = tree.root_node();
+    let proto = *protos(root)
+        .first()
+        .ok_or_else(|| anyhow!("No PROTO to expand."))?;
+
+    let header = Header::from_source(code);
+    if header
+        .template_language
+        .as_deref()
+        .is_some_and(|language| language != "javascript")
+    {
+        return Err(anyhow!("Only JavaScript templates can be expanded."));
+    }
+
+    let mut fields = Vec::new();
+    for field in interface(proto) {
+        let name = text(field.name, code);
+        let field_type = text(field.field_type, code);
+        let default = js_value(field_type, &[field.value], code);
+        let value = match values.get(name) {
+            Some(value) => parse_value(field_type, value)?,
+            None => default.clone(),
+        };
+        fields.push(format!(
+            "{name}: {{ value: {value}, defaultValue: {default} }}"
+        ));
+    }
+    if let Some(name) = values
+        .keys()
+        .find(|name| !interface(proto).iter().any(|f| text(f.name, code) == *name))
+    {
+        return Err(anyhow!("The PROTO has no field \"{name}\"."));
+    }
+
+    // The body is everything after the interface.
+    let mut cursor = proto.walk();
+    let body_start = proto
+        .children(&mut cursor)
+        .find(|child| child.kind() == "]")
+        .map_or(proto.start_byte(), |child| child.end_byte());
+    let body = &code[body_start..proto.end_byte()];
+
+    let script = [
+        format!("const fields = {{ {} }};", fields.join(", ")),
+        format!("const context = {};", js_context(path, &header, config)),
+        generate(body)?,
+    ]
+    .join("\n");
+    let generated = run(script.as_str(), config)?;
+
+    // The result no longer is a template.
+    let before: String = code[..body_start]
+        .split_inclusive('\n')
+        .filter(|line| !line.trim_start().starts_with("# template language:"))
+        .collect();
+    Ok(before + generated.as_str() + &code[proto.end_byte()..])
+}
+
+/// Turns a template into a JavaScript module appending the text it generates to
+/// `globalThis.__generated`. Imports are moved to the top, where modules need them.
+fn generate(template: &str) -> Result<String> {
+    let mut imports = Vec::new();
+    let mut statements = vec!["let __generated = '';".to_string()];
+    let mut rest = template;
+    while let Some(start) = rest.find("%<") {
+        statements.push(format!("__generated += {};", js_string(&rest[..start])));
+        let after = &rest[start + 2..];
+        let end = after
+            .find(">%")
+            .ok_or_else(|| anyhow!("Unterminated template block."))?;
+        let block = &after[..end];
+        if let Some(expression) = block.strip_prefix('=') {
+            statements.push(format!("__generated += ({});", expression.trim()));
+        } else {
+            for line in block.lines() {
+                if line.trim_start().starts_with("import ") {
+                    imports.push(line.trim().to_string());
+                } else {
+                    statements.push(line.to_string());
+                }
+            }
+        }
+        rest = &after[end + 2..];
+    }
+    statements.push(format!("__generated += {};", js_string(rest)));
+    statements.push("globalThis.__generated = __generated;".to_string());
+    Ok([imports, statements].concat().join("\n"))
+}
+
+/// Evaluates the module `script` and returns what it generated. Messages logged with `console`
+/// go to stderr.
+fn run(script: &str, config: &Config) -> Result<String> {
+    let runtime = Runtime::new()?;
+    if let Some(webots_home) = &config.externproto.webots_home {
+        let mut resolver = FileResolver::default();
+        for dir in MODULE_DIRS {
+            resolver.add_path(webots_home.join(dir).to_string_lossy().into_owned());
+        }
+        runtime.set_loader(resolver, ScriptLoader::default());
+    }
+    let context = Context::full(&runtime)?;
+
+    context.with(|ctx| {
+        let print = Function::new(ctx.clone(), |text: String| eprintln!("{text}"))?;
+        ctx.globals().set("__print", print)?;
+        ctx.eval::<(), _>(
+            "globalThis.console = { log: (...a) => __print(a.join(' ')) };
+             console.info = console.warn = console.error = console.log;",
+        )?;
+
+        Module::evaluate(ctx.clone(), "template", script)
+            .and_then(|promise| promise.finish::<()>())
+            .catch(&ctx)
+            .map_err(|err| anyhow!("Template error: {}", err.to_string().trim_end()))?;
+        Ok(ctx.globals().get::<_, String>("__generated")?)
+    })
+}
+
+/// The `context` object Webots gives templates.
+fn js_context(path: Option<&Path>, header: &Header, config: &Config) -> String {
+    let path = path.map(|path| path.canonicalize().unwrap_or(path.to_path_buf()));
+    let project = path
+        .as_deref()
+        .and_then(Path::parent)
+        .and_then(Path::parent)
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let path_string = |path: Option<&PathBuf>| {
+        js_string(
+            path.map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_default()
+                .as_str(),
+        )
+    };
+    let version = header
+        .release()
+        .map(|release| release.to_string())
+        .unwrap_or_default();
+    format!(
+        "{{ world: '', proto: {}, project_path: {}, webots_home: {}, temporary_files_path: '', \
+         os: {}, id: '0', coordinate_system: 'ENU', \
+         webots_version: {{ major: {}, revision: '0' }} }}",
+        path_string(path.as_ref()),
+        path_string(Some(&project)),
+        path_string(config.externproto.webots_home.as_ref()),
+        js_string(std::env::consts::OS),
+        js_string(version.as_str()),
+    )
+}
+
+/// Parses `value`, written as in a PROTO file, into the JavaScript value of a `field_type` field.
+fn parse_value(field_type: &str, value: &str) -> Result<String> {
+    let code =
+        format!("PROTO P [\n  field {field_type} value {value}\n]\n{{\n  Group {{\n  }}\n}}\n");
+    let tree = parse(code.as_str())?;
+    let field = protos(tree.root_node())
+        .first()
+        .and_then(|proto| {
+            proto
+                .named_children(&mut proto.walk())
+                .find(|n| n.kind() == "field")
+        })
+        .filter(|_| !tree.root_node().has_error())
+        .ok_or_else(|| anyhow!("Invalid {field_type} value \"{value}\"."))?;
+    let field = FieldDecl::new(field)?;
+    Ok(js_value(field_type, &[field.value], code.as_str()))
+}
+
+/// The JavaScript value of a field: numbers, booleans and strings as such, vectors as
+/// `{x, y, z}`, colors as `{r, g, b}`, rotations as `{x, y, z, a}`, nodes as
+/// `{node_name, fields}` and `MF` fields as arrays of those.
+pub fn js_value(field_type: &str, values: &[Node], code: &str) -> String {
+    let items = ValueShape::new(values).items;
+    let single = field_type.get(2..).unwrap_or_default();
+    let keys: &[&str] = match single {
+        "Vec2f" => &["x", "y"],
+        "Vec3f" => &["x", "y", "z"],
+        "Color" => &["r", "g", "b"],
+        "Rotation" => &["x", "y", "z", "a"],
+        _ => &[],
+    };
+
+    let converted: Vec<String> = if single == "Node" {
+        items.iter().map(|item| js_node(*item, code)).collect()
+    } else if keys.is_empty() {
+        items.iter().map(|item| js_literal(*item, code)).collect()
+    } else {
+        items
+            .chunks(keys.len())
+            .map(|chunk| {
+                let components: Vec<String> = keys
+                    .iter()
+                    .zip(chunk)
+                    .map(|(key, item)| format!("{key}: {}", js_literal(*item, code)))
+                    .collect();
+                format!("{{ {} }}", components.join(", "))
+            })
+            .collect()
+    };
+
+    if field_type.starts_with("MF") {
+        format!("[{}]", converted.join(", "))
+    } else {
+        converted
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| "null".to_string())
+    }
+}
+
+fn js_literal(node: Node, code: &str) -> String {
+    match text(node, code) {
+        "TRUE" => "true".to_string(),
+        "FALSE" => "false".to_string(),
+        "NULL" => "null".to_string(),
+        text => text.to_string(),
+    }
+}
+
+/// A node as `{node_name, fields}`. Only built-in nodes have their fields filled in, since the
+/// types of PROTO fields are unknown here.
+fn js_node(node: Node, code: &str) -> String {
+    let Some(node_type) = NodeForm::new(node).and_then(|form| form.node_type()) else {
+        return "null".to_string();
+    };
+    let node_type = text(node_type, code);
+
+    let mut cursor = node.walk();
+    let properties: Vec<Property> = node
+        .children(&mut cursor)
+        .filter(|child| child.kind() == "property")
+        .filter_map(Property::new)
+        .filter(|property| property.is_target.is_none())
+        .collect();
+    let mut fields = Vec::new();
+    for field in node_fields(node_type, None).unwrap_or_default() {
+        let default = field
+            .default
+            .and_then(|default| parse_value(field.field_type, default).ok())
+            .unwrap_or_else(|| "null".to_string());
+        let value = properties
+            .iter()
+            .find(|property| text(property.name, code) == field.name)
+            .map(|property| js_value(field.field_type, &property.values, code))
+            .unwrap_or_else(|| default.clone());
+        fields.push(format!(
+            "{}: {{ value: {value}, defaultValue: {default} }}",
+            field.name
+        ));
+    }
+    format!(
+        "{{ node_name: {}, fields: {{ {} }} }}",
+        js_string(node_type),
+        fields.join(", ")
+    )
+}
+
+fn js_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('\'');
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\'' => escaped.push_str("\\'"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{2028}' => escaped.push_str("\\u2028"),
+            '\u{2029}' => escaped.push_str("\\u2029"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('\'');
+    escaped
+}
+
+/// A small deterministic random number generator (SplitMix64), enough to sample field values.
+#[derive(Debug, Clone)]
+pub struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`, `n` being positive.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// A number between `low` and `high`, rounded to three decimals.
+    pub fn float(&mut self, low: f64, high: f64) -> f64 {
+        let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        ((low + unit * (high - low)) * 1000.0).round() / 1000.0
+    }
+
+    fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+        choices[self.below(choices.len())]
+    }
+}
+
+/// Random values for the interface fields of the PROTO in `code`, written as in a PROTO file.
+/// Fields with restrictions take one of the allowed values, the others a value of their type,
+/// sometimes their default.
+pub fn sample_values(code: &str, random: &mut Random) -> Result<BTreeMap<String, String>> {
+    let tree = parse(code)?;
+    let proto = *protos(tree.root_node())
+        .first()
+        .ok_or_else(|| anyhow!("No PROTO to sample."))?;
+
+    let mut values = BTreeMap::new();
+    for field in interface(proto) {
+        let name = text(field.name, code).to_string();
+        let default = text(field.value, code);
+        let value = if !field.restrictions.is_empty() {
+            let restriction = field.restrictions[random.below(field.restrictions.len())];
+            text(restriction, code).to_string()
+        } else if random.below(4) == 0 {
+            default.to_string()
+        } else {
+            sample(text(field.field_type, code), default, random)
+        };
+        values.insert(name, value);
+    }
+    Ok(values)
+}
+
+fn sample(field_type: &str, default: &str, random: &mut Random) -> String {
+    let mut numbers = |count: usize, low: f64, high: f64| {
+        let numbers: Vec<String> = (0..count)
+            .map(|_| random.float(low, high).to_string())
+            .collect();
+        numbers.join(" ")
+    };
+    match field_type {
+        "SFBool" => random.pick(&["TRUE", "FALSE"]).to_string(),
+        "SFInt32" => (random.below(21) as i64 - 10).to_string(),
+        "SFFloat" => numbers(1, -10.0, 10.0),
+        "SFVec2f" => numbers(2, -10.0, 10.0),
+        "SFVec3f" => numbers(3, -10.0, 10.0),
+        "SFColor" => numbers(3, 0.0, 1.0),
+        "SFRotation" => format!(
+            "0 0 1 {}",
+            random.float(-std::f64::consts::PI, std::f64::consts::PI)
+        ),
+        "SFString" => random.pick(&["\"\"", "\"sample\"", "\"a b\""]).to_string(),
+        "SFNode" => random.pick(&["NULL", default]).to_string(),
+        _ if field_type.starts_with("MF") => random.pick(&["[]", default]).to_string(),
+        _ => default.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(template: &str) -> Result<String> {
+        run(generate(template)?.as_str(), &Config::default())
+    }
+
+    #[test]
+    fn field_values_are_split_at_the_first_equal_sign() {
+        let arguments = ["size = 1 2 3".to_string(), "name=\"a=b\"".to_string()];
+        let values = field_values(&arguments).unwrap();
+        assert_eq!(values["size"], "1 2 3");
+        assert_eq!(values["name"], "\"a=b\"");
+        assert!(field_values(&["size".to_string()]).is_err());
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        assert_eq!(js_string("it's\n\\"), r"'it\'s\n\\'");
+    }
+
+    #[test]
+    fn templates_generate_their_text() {
+        assert_eq!(
+            evaluate("a %< for (let i = 0; i < 3; ++i) { >%x%<= i >% %< } >%'b'\n").unwrap(),
+            "a x0 x1 x2 'b'\n"
+        );
+        assert_eq!(
+            evaluate("%< console.log('to stderr'); const s = 'v'; >%%<= s + 1 >%").unwrap(),
+            "v1"
+        );
+    }
+
+    #[test]
+    fn template_errors_are_reported() {
+        assert_eq!(
+            evaluate("%< const x = 1;").unwrap_err().to_string(),
+            "Unterminated template block."
+        );
+        let error = evaluate("%< throw new Error('boom'); >%").unwrap_err();
+        assert!(error.to_string().starts_with("Template error:"));
+        assert!(error.to_string().contains("boom"));
+    }
+
+    const BOXES: &str = r#"#VRML_SIM R2023b utf8
+# template language: javascript
+
+PROTO Boxes [
+  field SFInt32 count 2
+  field SFVec3f size 1 1 1
+  field SFBool  visible TRUE
+]
+{
+  Group {
+    children [
+      %< for (let i = 0; i < fields.count.value; ++i) { >%
+      Solid { translation %<= i * fields.size.value.x >% 0 0 name "%<= fields.visible.value >%" }
+      %< } >%
+    ]
+  }
+}
+"#;
+
+    #[test]
+    fn expand_uses_the_default_values() {
+        let expanded = expand(BOXES, None, &BTreeMap::new(), &Config::default()).unwrap();
+        assert!(expanded.starts_with("#VRML_SIM R2023b utf8\n\nPROTO Boxes [\n"));
+        assert!(!expanded.contains("%<"));
+        assert_eq!(expanded.matches("Solid {").count(), 2);
+        assert!(expanded.contains("Solid { translation 0 0 0 name \"true\" }"));
+        assert!(expanded.contains("Solid { translation 1 0 0 name \"true\" }"));
+        assert!(expanded.ends_with("    ]\n  }\n}\n"));
+    }
+
+    #[test]
+    fn expand_uses_the_given_values() {
+        let arguments = [
+            "count=3".to_string(),
+            "size=2 1 1".to_string(),
+            "visible=FALSE".to_string(),
+        ];
+        let values = field_values(&arguments).unwrap();
+        let expanded = expand(BOXES, None, &values, &Config::default()).unwrap();
+        assert_eq!(expanded.matches("Solid {").count(), 3);
+        assert!(expanded.contains("Solid { translation 4 0 0 name \"false\" }"));
+
+        let values = field_values(&["color=1 0 0".to_string()]).unwrap();
+        assert_eq!(
+            expand(BOXES, None, &values, &Config::default())
+                .unwrap_err()
+                .to_string(),
+            "The PROTO has no field \"color\"."
+        );
+        let values = field_values(&["count=two".to_string()]).unwrap();
+        assert!(expand(BOXES, None, &values, &Config::default()).is_err());
+    }
+
+    #[test]
+    fn field_values_convert_to_javascript() {
+        let code = "PROTO P [\n  field MFVec2f points [ 0 1, 2 3 ]\n  field SFNode shape Shape { castShadows FALSE }\n]\n{\n  Group {\n  }\n}\n";
+        let tree = parse(code).unwrap();
+        let proto = protos(tree.root_node())[0];
+        let fields = interface(proto);
+        assert_eq!(
+            js_value("MFVec2f", &[fields[0].value], code),
+            "[{ x: 0, y: 1 }, { x: 2, y: 3 }]"
+        );
+        let shape = js_value("SFNode", &[fields[1].value], code);
+        assert!(shape.starts_with("{ node_name: 'Shape', fields: { "));
+        assert!(shape.contains("castShadows: { value: false, defaultValue: true }"));
+    }
+
+    #[test]
+    fn random_numbers_are_deterministic() {
+        let (mut a, mut b) = (Random::new(7), Random::new(7));
+        let first: Vec<u64> = (0..4).map(|_| a.next_u64()).collect();
+        let second: Vec<u64> = (0..4).map(|_| b.next_u64()).collect();
+        assert_eq!(first, second);
+        assert_ne!(Random::new(8).next_u64(), first[0]);
+
+        let mut random = Random::new(1);
+        for _ in 0..100 {
+            assert!(random.below(3) < 3);
+            let x = random.float(-1.0, 1.0);
+            assert!((-1.0..=1.0).contains(&x));
+            assert_eq!((x * 1000.0).round() / 1000.0, x);
+        }
+    }
+
+    #[test]
+    fn samples_are_values_of_the_field_type() {
+        let mut random = Random::new(3);
+        for _ in 0..50 {
+            let color = sample("SFColor", "1 1 1", &mut random);
+            let components: Vec<f64> = color
+                .split_whitespace()
+                .map(|c| c.parse().unwrap())
+                .collect();
+            assert_eq!(components.len(), 3);
+            assert!(components.iter().all(|c| (0.0..=1.0).contains(c)));
+            assert!(["TRUE", "FALSE"].contains(&sample("SFBool", "TRUE", &mut random).as_str()));
+            assert!(["[]", "[ 1 2 ]"].contains(&sample("MFFloat", "[ 1 2 ]", &mut random).as_str()));
+            assert_eq!(sample("SFImage", "x", &mut random), "x");
+        }
+    }
+
+    #[test]
+    fn samples_respect_restrictions() {
+        let code = r#"#VRML_SIM R2023b utf8
+PROTO Lamp [
+  field SFString{"low", "high"} quality "low"
+  field SFFloat power 1
+]
+{
+  Group {
+  }
+}
+"#;
+        let mut random = Random::new(5);
+        for _ in 0..20 {
+            let values = sample_values(code, &mut random).unwrap();
+            assert!(["\"low\"", "\"high\""].contains(&values["quality"].as_str()));
+            assert!(values["power"].parse::<f64>().is_ok());
+        }
+    }
+}
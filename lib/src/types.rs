@@ -0,0 +1,330 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use tree_sitter::Node;
+
+use super::interface::{interface, protos};
+use super::lint::{Context, Report, Rule, Severity};
+use super::schema::field_type;
+use super::syntax::{parent_node_type, Property};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Literal {
+    Bool,
+    Int,
+    Float,
+    String,
+    Null,
+    Node,
+    Template,
+    Other,
+}
+
+pub fn classify(node: Node, code: &str) -> Literal {
+    match node.kind() {
+        "node" => return Literal::Node,
+        "javascript_block" | "javascript_expression" => return Literal::Template,
+        _ => {}
+    }
+    let text = node.utf8_text(code.as_bytes()).unwrap_or_default();
+    let digits = text.trim_start_matches(['-', '+']);
+    if text == "TRUE" || text == "FALSE" {
+        Literal::Bool
+    } else if text == "NULL" {
+        Literal::Null
+    } else if text.starts_with('"') {
+        Literal::String
+    } else if digits.parse::<u64>().is_ok()
+        || digits
+            .strip_prefix("0x")
+            .or_else(|| digits.strip_prefix("0X"))
+            .is_some_and(|hex| u64::from_str_radix(hex, 16).is_ok())
+    {
+        Literal::Int
+    } else if text.parse::<f64>().is_ok() {
+        Literal::Float
+    } else {
+        Literal::Other
+    }
+}
+
+/// The literals making up a value, and whether they were written between brackets.
+#[derive(Debug, Clone)]
+pub struct ValueShape<'a> {
+    pub bracketed: bool,
+    pub items: Vec<Node<'a>>,
+}
+
+impl<'a> ValueShape<'a> {
+    pub fn new(values: &[Node<'a>]) -> ValueShape<'a> {
+        let bracketed = values.len() == 1 && has_bracket(values[0]);
+        let mut items = Vec::new();
+        for value in values {
+            leaves(*value, &mut items);
+        }
+        ValueShape { bracketed, items }
+    }
+}
+
+fn has_bracket(node: Node) -> bool {
+    node.kind() == "vector" && node.child(0).is_some_and(|c| c.kind() == "[")
+}
+
+fn leaves<'a>(node: Node<'a>, items: &mut Vec<Node<'a>>) {
+    match node.kind() {
+        "[" | "]" | "," | "comment" => {}
+        "vector" => {
+            let mut cursor = node.walk();
+            for child in node.children(&mut cursor) {
+                leaves(child, items);
+            }
+        }
+        _ => items.push(node),
+    }
+}
+
+/// How many literals make a single value of `field_type`, and what they look like.
+pub fn components(field_type: &str) -> Option<(usize, &'static str)> {
+    let single = field_type
+        .strip_prefix("SF")
+        .or(field_type.strip_prefix("MF"))?;
+    Some(match single {
+        "Bool" => (1, "TRUE or FALSE"),
+        "Int32" => (1, "an integer"),
+        "Float" => (1, "a number"),
+        "String" => (1, "a quoted string"),
+        "Vec2f" => (2, "2 numbers"),
+        "Vec3f" => (3, "3 numbers"),
+        "Color" => (3, "3 numbers"),
+        "Rotation" => (4, "4 numbers"),
+        "Node" => (1, "a node"),
+        _ => return None,
+    })
+}
+
+fn matches(field_type: &str, literal: Literal) -> bool {
+    match &field_type[2..] {
+        "Bool" => literal == Literal::Bool,
+        "Int32" => literal == Literal::Int,
+        "String" => literal == Literal::String,
+        "Node" => literal == Literal::Node || (field_type == "SFNode" && literal == Literal::Null),
+        _ => matches!(literal, Literal::Int | Literal::Float),
+    }
+}
+
+/// Checks that `values` are a valid value of `field_type`, returning what is wrong otherwise.
+/// Values containing template code are not checked, nor are unknown types.
+pub fn check_value(field_type: &str, values: &[Node], code: &str) -> Result<(), String> {
+    let Some((count, description)) = components(field_type) else {
+        return Ok(());
+    };
+    let shape = ValueShape::new(values);
+    let literals: Vec<Literal> = shape.items.iter().map(|n| classify(*n, code)).collect();
+    if literals.contains(&Literal::Template) {
+        return Ok(());
+    }
+
+    // A single item of a multiple value field may be written without brackets, as in
+    // `url "textures/a.png"` or `children Solid {}`.
+    let multiple = field_type.starts_with("MF");
+    if multiple && !shape.bracketed && literals.len() > count {
+        return Err(format!(
+            "{field_type} values with several items must be written between brackets"
+        ));
+    }
+    if !multiple && shape.bracketed {
+        return Err(format!(
+            "{field_type} values cannot be written between brackets"
+        ));
+    }
+
+    if let Some(literal) = literals.iter().find(|l| !matches(field_type, **l)) {
+        let found = match literal {
+            Literal::Null => "NULL",
+            Literal::Node => "a node",
+            Literal::Bool => "a boolean",
+            Literal::String => "a string",
+            Literal::Float => "a number",
+            _ => "an invalid value",
+        };
+        return Err(format!("{field_type} expects {description}, found {found}"));
+    }
+
+    let valid = if multiple {
+        literals.len().checked_rem(count) == Some(0)
+    } else {
+        literals.len() == count
+    };
+    if !valid {
+        return Err(format!(
+            "{field_type} expects {description}{}, found {} value(s)",
+            if multiple { " per item" } else { "" },
+            literals.len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Interface defaults and node field values that do not match their declared type.
+pub struct ValueType;
+
+impl Rule for ValueType {
+    fn id(&self) -> &'static str {
+        "value-type"
+    }
+
+    fn description(&self) -> &'static str {
+        "field value does not match the field type"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, context: &Context, report: &mut Report) {
+        for proto in protos(context.root) {
+            for field in interface(proto) {
+                let field_type = context.text(field.field_type);
+                if let Err(message) = check_value(field_type, &[field.value], context.code) {
+                    report.add(field.value.range(), message);
+                }
+            }
+        }
+
+        let mut stack = vec![context.root];
+        while let Some(node) = stack.pop() {
+            let mut cursor = node.walk();
+            stack.extend(node.children(&mut cursor));
+            if node.kind() != "property" {
+                continue;
+            }
+            let Some(property) = Property::new(node).filter(|p| p.is_target.is_none()) else {
+                continue;
+            };
+            let Some(node_type) = parent_node_type(node) else {
+                continue;
+            };
+            let node_type = context.text(node_type);
+            let field = context.text(property.name);
+            let Some(field_type) = field_type(node_type, field, context.release) else {
+                continue;
+            };
+            if let Err(message) = check_value(field_type, &property.values, context.code) {
+                report.add(node.range(), message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lint::tests::messages;
+    use crate::syntax::parse;
+
+    /// Checks `value`, written as the value of a node field, against `field_type`.
+    fn check(field_type: &str, value: &str) -> Result<(), String> {
+        let code = format!("Group {{\n  value {value}\n}}\n");
+        let tree = parse(&code).unwrap();
+        let mut stack = vec![tree.root_node()];
+        while let Some(node) = stack.pop() {
+            if node.kind() == "property" {
+                let property = Property::new(node).unwrap();
+                return check_value(field_type, &property.values, &code);
+            }
+            let mut cursor = node.walk();
+            stack.extend(node.children(&mut cursor));
+        }
+        panic!("no property in {code}");
+    }
+
+    #[test]
+    fn components_of_types() {
+        assert_eq!(components("SFVec3f"), Some((3, "3 numbers")));
+        assert_eq!(components("MFRotation"), Some((4, "4 numbers")));
+        assert_eq!(components("SFImage"), None);
+    }
+
+    #[test]
+    fn single_values() {
+        assert_eq!(check("SFVec3f", "0 1 2.5"), Ok(()));
+        assert_eq!(check("SFBool", "TRUE"), Ok(()));
+        assert_eq!(check("SFInt32", "0x1F"), Ok(()));
+        assert_eq!(check("SFNode", "NULL"), Ok(()));
+        assert_eq!(
+            check("SFVec3f", "0 1"),
+            Err("SFVec3f expects 3 numbers, found 2 value(s)".to_string())
+        );
+        assert_eq!(
+            check("SFInt32", "1.5"),
+            Err("SFInt32 expects an integer, found a number".to_string())
+        );
+        assert_eq!(
+            check("SFString", "[ \"a\" ]"),
+            Err("SFString values cannot be written between brackets".to_string())
+        );
+    }
+
+    #[test]
+    fn multiple_values() {
+        assert_eq!(check("MFVec3f", "[ 0 0 0, 1 1 1 ]"), Ok(()));
+        assert_eq!(check("MFString", "[ ]"), Ok(()));
+        assert_eq!(check("MFNode", "[ Solid { } Shape { } ]"), Ok(()));
+        assert_eq!(
+            check("MFVec2f", "[ 0 0 1 ]"),
+            Err("MFVec2f expects 2 numbers per item, found 3 value(s)".to_string())
+        );
+        assert_eq!(
+            check("MFNode", "[ NULL ]"),
+            Err("MFNode expects a node, found NULL".to_string())
+        );
+    }
+
+    #[test]
+    fn single_item_of_multiple_values_may_be_unbracketed() {
+        assert_eq!(check("MFString", "\"textures/a.png\""), Ok(()));
+        assert_eq!(check("MFNode", "Solid { }"), Ok(()));
+        assert_eq!(check("MFVec3f", "0 0 1"), Ok(()));
+        assert_eq!(
+            check("MFFloat", "1 2"),
+            Err("MFFloat values with several items must be written between brackets".to_string())
+        );
+    }
+
+    #[test]
+    fn template_values_are_not_checked() {
+        assert_eq!(check("SFVec3f", "%<= fields.size.value.x >% 0 0"), Ok(()));
+    }
+
+    #[test]
+    fn value_type_rule() {
+        let code = r#"#VRML_SIM R2023b utf8
+PROTO Box [
+  field SFVec3f size 1 1
+  field MFString url "box.png"
+]
+{
+  Solid {
+    name 3
+    children Shape {
+    }
+    locked TRUE
+  }
+}
+"#;
+        assert_eq!(
+            messages("value-type", code),
+            [
+                (3, "SFVec3f expects 3 numbers, found 2 value(s)".to_string()),
+                (
+                    8,
+                    "SFString expects a quoted string, found a number".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,515 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use tree_sitter::Node;
+
+use super::config::Config;
+use super::edit::{apply_edits, Edit};
+use super::externproto::WEBOTS_GITHUB;
+use super::header::{Header, Release};
+use super::lint::{lint, rules, Context, Severity};
+use super::schema::{node_schema, Change, HISTORY};
+use super::syntax::{parse_valid, NodeForm, Property};
+
+/// The release requiring every instantiated PROTO to be declared and introducing `Pose`.
+const R2023B: Release = Release {
+    year: 2023,
+    revision: 'b',
+};
+
+/// A rewrite of files written for an older Webots release.
+pub trait Migration {
+    fn description(&self) -> &'static str;
+    /// The edits upgrading the file of `context` from the release `from` to `to`.
+    fn edits(&self, context: &Context, from: Release, to: Release) -> Result<Vec<Edit>>;
+}
+
+/// All migrations, in the order they run.
+pub fn migrations() -> Vec<Box<dyn Migration>> {
+    vec![
+        Box::new(HeaderRelease),
+        Box::new(ReleaseUrls),
+        Box::new(RenamedFields),
+        Box::new(TransformToPose),
+        Box::new(DeclareExternProtos),
+    ]
+}
+
+/// Upgrades `code` from the release `from`, or the one of its header if there is none, to `to`.
+/// Each migration runs on the tree of the code the previous one left. The result is still to be
+/// formatted.
+pub fn upgrade(
+    code: &str,
+    path: Option<&Path>,
+    config: &Config,
+    from: Option<Release>,
+    to: Release,
+) -> Result<String> {
+    let from = from
+        .or_else(|| Header::from_source(code).release())
+        .ok_or_else(|| anyhow!("No release to upgrade from in the header, give it with --from."))?;
+    if from > to {
+        return Err(anyhow!("Cannot downgrade from {from} to {to}."));
+    }
+
+    let mut code = code.to_string();
+    let mut previous = None;
+    for migration in migrations() {
+        let tree = parse_valid(code.as_str()).map_err(|err| match previous {
+            Some(description) => anyhow!("{err} After: {description}."),
+            None => err,
+        })?;
+        let root = tree.root_node();
+        let context = Context {
+            code: code.as_str(),
+            root,
+            path,
+            config,
+            release: Header::from_source(code.as_str()).release(),
+        };
+        let edits = migration.edits(&context, from, to)?;
+        code = apply_edits(code.as_str(), &edits);
+        previous = Some(migration.description());
+    }
+    Ok(code)
+}
+
+/// Sets the release of the `#VRML_SIM` header, adding the header if there is none.
+pub struct HeaderRelease;
+
+impl Migration for HeaderRelease {
+    fn description(&self) -> &'static str {
+        "update the #VRML_SIM header"
+    }
+
+    fn edits(&self, context: &Context, _: Release, to: Release) -> Result<Vec<Edit>> {
+        let mut start = 0;
+        for line in context.code.split_inclusive('\n') {
+            let text = line.trim();
+            if !text.is_empty() && !text.starts_with('#') {
+                break;
+            }
+            if text.starts_with("#VRML_SIM") {
+                let indent = line.len() - line.trim_start().len();
+                let version_start = start + indent + "#VRML_SIM".len();
+                let rest = &context.code[version_start..];
+                let spaces = rest.len() - rest.trim_start().len();
+                let version_start = version_start + spaces;
+                let version = context.code[version_start..]
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default();
+                if version == to.to_string() {
+                    return Ok(Vec::new());
+                }
+                return Ok(vec![Edit {
+                    start: version_start,
+                    end: version_start + version.len(),
+                    replacement: to.to_string(),
+                }]);
+            }
+            start += line.len();
+        }
+        Ok(vec![Edit::insert(0, format!("#VRML_SIM {to} utf8\n"))])
+    }
+}
+
+/// Points the URLs of the Webots repository, in EXTERNPROTOs and strings like texture URLs, to the
+/// new release. Only the URLs of releases from the one upgraded from up to the new one are updated,
+/// as others were chosen on purpose.
+pub struct ReleaseUrls;
+
+impl Migration for ReleaseUrls {
+    fn description(&self) -> &'static str {
+        "update the release of Webots URLs"
+    }
+
+    fn edits(&self, context: &Context, from: Release, to: Release) -> Result<Vec<Edit>> {
+        let mut edits = Vec::new();
+        let mut stack = vec![context.root];
+        while let Some(node) = stack.pop() {
+            if matches!(node.kind(), "javascript_block" | "javascript_expression") {
+                continue;
+            }
+            let text = context.text(node);
+            if node.child_count() > 0 || !text.starts_with('"') {
+                let mut cursor = node.walk();
+                stack.extend(node.children(&mut cursor));
+                continue;
+            }
+            for (i, _) in text.match_indices(WEBOTS_GITHUB) {
+                let tag_start = i + WEBOTS_GITHUB.len();
+                let tag = text[tag_start..].split('/').next().unwrap_or_default();
+                if Release::parse(tag).is_some_and(|release| from <= release && release < to) {
+                    let start = node.start_byte() + tag_start;
+                    edits.push(Edit {
+                        start,
+                        end: start + tag.len(),
+                        replacement: to.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(edits)
+    }
+}
+
+/// Renames the fields of built-in nodes renamed between the two releases, like `data` which became
+/// `customData` in R2022b.
+pub struct RenamedFields;
+
+impl Migration for RenamedFields {
+    fn description(&self) -> &'static str {
+        "rename the fields renamed by Webots"
+    }
+
+    fn edits(&self, context: &Context, from: Release, to: Release) -> Result<Vec<Edit>> {
+        // The node owning the field and its new name, by old name, oldest renames first.
+        let mut renames: HashMap<&str, Vec<(&str, &str)>> = HashMap::new();
+        for revision in HISTORY
+            .iter()
+            .filter(|r| from < r.release && r.release <= to)
+        {
+            if let (Change::Renamed { from }, Some(field)) = (revision.change, revision.field) {
+                renames
+                    .entry(from)
+                    .or_default()
+                    .push((revision.node, field));
+            }
+        }
+
+        let mut edits = Vec::new();
+        for (property, node_type) in properties(context) {
+            let name = context.text(property.name);
+            let renamed = renames.get(name).and_then(|renames| {
+                renames
+                    .iter()
+                    .find(|(node, _)| inherits(node_type, node))
+                    .map(|(_, field)| *field)
+            });
+            if let Some(renamed) = renamed {
+                edits.push(Edit {
+                    start: property.name.start_byte(),
+                    end: property.name.end_byte(),
+                    replacement: renamed.to_string(),
+                });
+            }
+        }
+        Ok(edits)
+    }
+}
+
+/// Turns the `Transform` nodes that do not scale into the `Pose` nodes of R2023b.
+pub struct TransformToPose;
+
+impl Migration for TransformToPose {
+    fn description(&self) -> &'static str {
+        "convert Transform nodes without scale to Pose"
+    }
+
+    fn edits(&self, context: &Context, from: Release, to: Release) -> Result<Vec<Edit>> {
+        if !(from < R2023B && R2023B <= to) {
+            return Ok(Vec::new());
+        }
+
+        let mut edits = Vec::new();
+        let mut stack = vec![context.root];
+        while let Some(node) = stack.pop() {
+            let mut cursor = node.walk();
+            stack.extend(node.children(&mut cursor));
+            let Some(node_type) = NodeForm::new(node)
+                .filter(|_| node.kind() == "node")
+                .and_then(|form| form.node_type())
+                .filter(|node_type| context.text(*node_type) == "Transform")
+            else {
+                continue;
+            };
+
+            let mut cursor = node.walk();
+            let children: Vec<Node> = node.children(&mut cursor).collect();
+            if children
+                .iter()
+                .any(|child| matches!(child.kind(), "javascript_block" | "javascript_expression"))
+            {
+                continue;
+            }
+            let scale = children
+                .iter()
+                .filter(|child| child.kind() == "property")
+                .filter_map(|child| Property::new(*child).map(|property| (*child, property)))
+                .find(|(_, property)| context.text(property.name) == "scale");
+            let mut node_edits = Vec::new();
+            if let Some((child, property)) = scale {
+                let unit = property.is_target.is_none()
+                    && match (property.values.first(), property.values.last()) {
+                        (Some(first), Some(last)) => {
+                            is_unit_scale(&context.code[first.start_byte()..last.end_byte()])
+                        }
+                        _ => false,
+                    };
+                if !unit {
+                    continue;
+                }
+                node_edits.push(Edit::delete_line(
+                    context.code,
+                    child.start_byte(),
+                    child.end_byte(),
+                ));
+            }
+            node_edits.push(Edit {
+                start: node_type.start_byte(),
+                end: node_type.end_byte(),
+                replacement: "Pose".to_string(),
+            });
+            edits.extend(node_edits);
+        }
+        Ok(edits)
+    }
+}
+
+/// Declares the PROTOs instantiated without EXTERNPROTO, required since R2023b, with the
+/// `missing-externproto` fix.
+pub struct DeclareExternProtos;
+
+impl Migration for DeclareExternProtos {
+    fn description(&self) -> &'static str {
+        "add the missing EXTERNPROTO declarations"
+    }
+
+    fn edits(&self, context: &Context, from: Release, to: Release) -> Result<Vec<Edit>> {
+        if !(from < R2023B && R2023B <= to) {
+            return Ok(Vec::new());
+        }
+        let severities = rules()
+            .iter()
+            .map(|rule| match rule.id() {
+                "missing-externproto" => (rule.id(), Severity::Error),
+                id => (id, Severity::Off),
+            })
+            .collect();
+        let diagnostics = lint(context.code, context.path, context.config, &severities)?;
+        Ok(diagnostics
+            .into_iter()
+            .flat_map(|diagnostic| diagnostic.fix)
+            .collect())
+    }
+}
+
+/// Every property of a node that is not a `USE`, with the node type.
+fn properties<'a>(context: &Context<'a>) -> Vec<(Property<'a>, &'a str)> {
+    let mut properties = Vec::new();
+    let mut stack = vec![context.root];
+    while let Some(node) = stack.pop() {
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor));
+        if node.kind() != "property" {
+            continue;
+        }
+        let node_type = node
+            .parent()
+            .filter(|parent| parent.kind() == "node")
+            .and_then(NodeForm::new)
+            .and_then(|form| form.node_type());
+        if let (Some(property), Some(node_type)) = (Property::new(node), node_type) {
+            properties.push((property, context.text(node_type)));
+        }
+    }
+    properties
+}
+
+/// Whether the built-in node `node_type` is `base` or derives from it.
+fn inherits(node_type: &str, base: &str) -> bool {
+    let mut schema = node_schema(node_type);
+    while let Some(node) = schema {
+        if node.name == base {
+            return true;
+        }
+        schema = node.base.and_then(node_schema);
+    }
+    false
+}
+
+fn is_unit_scale(value: &str) -> bool {
+    let components: Vec<&str> = value.split_whitespace().collect();
+    components.len() == 3
+        && components
+            .iter()
+            .all(|component| component.parse::<f64>().is_ok_and(|x| x == 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn release(text: &str) -> Release {
+        Release::parse(text).unwrap()
+    }
+
+    fn upgrade_to(code: &str, from: Option<&str>, to: &str) -> Result<String> {
+        upgrade(
+            code,
+            None,
+            &Config::default(),
+            from.map(release),
+            release(to),
+        )
+    }
+
+    #[test]
+    fn header_is_set_to_the_new_release() {
+        assert_eq!(
+            upgrade_to("#VRML_SIM R2022a utf8\nSolid {\n}\n", None, "R2022b").unwrap(),
+            "#VRML_SIM R2022b utf8\nSolid {\n}\n"
+        );
+        assert_eq!(
+            upgrade_to("Solid {\n}\n", Some("R2022a"), "R2022b").unwrap(),
+            "#VRML_SIM R2022b utf8\nSolid {\n}\n"
+        );
+        assert_eq!(
+            upgrade_to("#VRML_SIM R2022b utf8\nSolid {\n}\n", None, "R2022b").unwrap(),
+            "#VRML_SIM R2022b utf8\nSolid {\n}\n"
+        );
+    }
+
+    #[test]
+    fn missing_releases_and_downgrades_are_refused() {
+        assert_eq!(
+            upgrade_to("Solid {\n}\n", None, "R2023b")
+                .unwrap_err()
+                .to_string(),
+            "No release to upgrade from in the header, give it with --from."
+        );
+        assert_eq!(
+            upgrade_to("#VRML_SIM R2023b utf8\n", None, "R2022a")
+                .unwrap_err()
+                .to_string(),
+            "Cannot downgrade from R2023b to R2022a."
+        );
+    }
+
+    #[test]
+    fn only_urls_of_releases_between_the_two_are_updated() {
+        let code = r#"#VRML_SIM R2022a utf8
+ImageTexture {
+  url [
+    "https://raw.githubusercontent.com/cyberbotics/webots/R2021b/a.png"
+    "https://raw.githubusercontent.com/cyberbotics/webots/R2022a/b.png"
+    "https://raw.githubusercontent.com/cyberbotics/webots/R2022b/c.png"
+    "https://raw.githubusercontent.com/cyberbotics/webots/R2023b/d.png"
+    "https://example.com/R2022a/e.png"
+  ]
+  filtering %<= "https://raw.githubusercontent.com/cyberbotics/webots/R2022a/f.png".length >%
+}
+"#;
+        let upgraded = upgrade_to(code, None, "R2023a").unwrap();
+        let expected = code
+            .replacen("R2022a utf8", "R2023a utf8", 1)
+            .replacen("webots/R2022a/b.png", "webots/R2023a/b.png", 1)
+            .replacen("webots/R2022b/c.png", "webots/R2023a/c.png", 1);
+        assert_eq!(upgraded, expected);
+    }
+
+    #[test]
+    fn renamed_fields_of_built_in_nodes_are_renamed() {
+        let code = r#"#VRML_SIM R2022a utf8
+Robot {
+  data "a"
+  children [
+    Mavic2Pro {
+      data "b"
+    }
+  ]
+}
+"#;
+        let upgraded = upgrade_to(code, None, "R2022b").unwrap();
+        assert_eq!(
+            upgraded,
+            code.replacen("R2022a", "R2022b", 1)
+                .replacen("data \"a\"", "customData \"a\"", 1)
+        );
+        let later = code.replacen("R2022a", "R2022b", 1);
+        assert_eq!(
+            upgrade_to(&later, None, "R2023a").unwrap(),
+            later.replacen("R2022b", "R2023a", 1)
+        );
+    }
+
+    #[test]
+    fn transforms_without_scale_become_poses() {
+        let code = r#"#VRML_SIM R2023a utf8
+PROTO Scaled [
+  field SFVec3f size 1 1 1
+]
+{
+  Group {
+    children [
+      Transform {
+        translation 1 0 0
+      }
+      DEF UNIT Transform {
+        scale 1.0 1 1
+        children [
+        ]
+      }
+      Transform {
+        scale 2 2 2
+      }
+      Transform {
+        scale IS size
+      }
+      Transform {
+        %< if (fields.size.value.x > 1) { >%
+        scale 2 2 2
+        %< } >%
+      }
+    ]
+  }
+}
+"#;
+        let upgraded = upgrade_to(code, None, "R2023b").unwrap();
+        let expected = code
+            .replacen("R2023a", "R2023b", 1)
+            .replacen(
+                "Transform {\n        translation",
+                "Pose {\n        translation",
+                1,
+            )
+            .replacen(
+                "DEF UNIT Transform {\n        scale 1.0 1 1\n",
+                "DEF UNIT Pose {\n",
+                1,
+            );
+        assert_eq!(upgraded, expected);
+
+        let later = code.replacen("R2023a", "R2023b", 1);
+        assert_eq!(
+            upgrade_to(&later, None, "R2024a").unwrap(),
+            later.replacen("R2023b", "R2024a", 1)
+        );
+    }
+
+    #[test]
+    fn protos_are_declared_when_crossing_r2023b() {
+        let dir = TempDir::new("upgrade");
+        dir.write("Wall.proto", "");
+        let world = dir.join("room.wbt");
+        let code = "#VRML_SIM R2023a utf8\n\nWall {\n}\n";
+        let config = Config::default();
+
+        let upgraded = upgrade(code, Some(&world), &config, None, release("R2023b")).unwrap();
+        assert_eq!(
+            upgraded,
+            "#VRML_SIM R2023b utf8\n\nEXTERNPROTO \"Wall.proto\"\n\nWall {\n}\n"
+        );
+        let before = upgrade(code, Some(&world), &config, None, release("R2023a")).unwrap();
+        assert_eq!(before, code);
+    }
+}